@@ -0,0 +1,38 @@
+use std::io;
+
+use crate::il;
+
+// The generated source, as units that are built separately, and the header of a library's entry
+// points. Only large programs are split into more than one unit.
+#[derive(Clone, Debug)]
+pub struct Generated {
+    pub units: Vec<String>,
+    pub header: Option<String>,
+    // The backend's extensions, which the build names its files with
+    pub extension: &'static str,
+    pub header_extension: &'static str,
+}
+
+// Generates a target language from the IL. Building what a backend writes, as with the C
+// compiler for C, is a separate step.
+pub trait Backend {
+    // The extension of the generated source, as in out.c
+    fn extension(&self) -> &'static str;
+
+    // The extension of a library's header, as in out.h
+    fn header_extension(&self) -> &'static str;
+
+    fn generate(&self, program: il::Program, out: &mut dyn io::Write) -> io::Result<()>;
+
+    // The declarations of a library's entry points, for the code that calls them
+    fn header(&self, program: &il::Program, out: &mut dyn io::Write) -> io::Result<()>;
+
+    // The program as units that are built separately. A backend that doesn't split programs
+    // generates a single one.
+    fn generate_units(&self, program: il::Program) -> Vec<String> {
+        // Writing to memory can't fail
+        let mut code = Vec::new();
+        self.generate(program, &mut code).unwrap();
+        vec![String::from_utf8(code).unwrap()]
+    }
+}
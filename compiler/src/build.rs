@@ -0,0 +1,494 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::UNIX_EPOCH;
+
+use crate::backend::Generated;
+use crate::diagnostic::{Code, Diagnostic, Emitter, Severity};
+use crate::loc::{Loc, Span};
+use crate::runtime::{cc_command, fnv1a, Runtime, FNV_OFFSET, HEADERS};
+
+// How the generated C source is built, with the C compiler
+#[derive(Clone, Debug)]
+pub struct Options {
+    // Extra objects and libraries for the link line
+    pub link: Vec<String>,
+    // Intermediate files, and outputs that aren't named otherwise, go here
+    pub build_dir: PathBuf,
+    // The executable or library
+    pub output: Option<PathBuf>,
+    // Build the C code unoptimised and with debug information
+    pub debug: bool,
+    // Build the C code with debug information, whatever the optimisation level
+    pub debug_info: bool,
+    // The C compiler, and the flags from the command line added to its own
+    pub cc: String,
+    pub cflags: Vec<String>,
+    pub ldflags: Vec<String>,
+    // Sanitizers the program is instrumented with, against a runtime instrumented the same way
+    pub sanitize: Option<String>,
+    // An ic repository whose runtime is used instead of the one built into ic
+    pub ic_home: Option<PathBuf>,
+    // What the C source was generated from, as given by `ic::fingerprint`, for `ic::up_to_date`
+    pub fingerprint: Option<u64>,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            link: Vec::new(),
+            build_dir: PathBuf::from("_build"),
+            output: None,
+            debug: false,
+            debug_info: false,
+            cc: "gcc".to_string(),
+            cflags: Vec::new(),
+            ldflags: Vec::new(),
+            sanitize: None,
+            ic_home: None,
+            fingerprint: None,
+        }
+    }
+}
+
+impl Options {
+    // out.c, then out1.c, out2.c... with the backend's extension
+    fn unit_path(&self, unit: usize, extension: &str) -> PathBuf {
+        match unit {
+            0 => self.build_dir.join(format!("out.{}", extension)),
+            _ => self.build_dir.join(format!("out{}.{}", unit, extension)),
+        }
+    }
+
+    fn exe_path(&self) -> PathBuf {
+        self.output
+            .clone()
+            .unwrap_or_else(|| self.build_dir.join("out"))
+    }
+
+    fn lib_path(&self) -> PathBuf {
+        self.output
+            .clone()
+            .unwrap_or_else(|| self.build_dir.join("libout.a"))
+    }
+
+    // The command line's flags come last, to override ours
+    fn cflags(&self) -> Vec<String> {
+        let mut flags = vec![if self.debug { "-O0" } else { "-O3" }.to_string()];
+        if self.debug || self.debug_info || self.sanitize.is_some() {
+            flags.push("-g".to_string());
+        }
+        if let Some(sanitizers) = &self.sanitize {
+            flags.push("-fno-omit-frame-pointer".to_string());
+            flags.push(format!("-fsanitize={}", sanitizers));
+        }
+        flags.extend(self.cflags.iter().cloned());
+        flags
+    }
+}
+
+// Stamps in the build directory of what its files were last built from
+const OBJECTS_KEY: &str = "objects.key";
+const OUTPUT_KEY: &str = "output.key";
+const PROGRAM_KEY: &str = "program.key";
+
+pub struct Builder<'a> {
+    options: &'a Options,
+    emitter: &'a Emitter<'a>,
+}
+
+impl<'a> Builder<'a> {
+    pub fn new(options: &'a Options, emitter: &'a Emitter<'a>) -> Self {
+        Self { options, emitter }
+    }
+
+    // The runtime of IC_HOME, or the one built into ic
+    pub fn runtime(&self) -> Option<Runtime> {
+        let sanitize = self.options.sanitize.as_deref();
+        match &self.options.ic_home {
+            Some(ic_home) => Runtime::from_home(ic_home, &self.options.cc, sanitize, self.emitter),
+            None => Runtime::cached(&self.options.cc, sanitize, self.emitter),
+        }
+    }
+
+    // Whether the output was built from a program of the same fingerprint, with the same options
+    // and runtime, and is still there
+    pub fn is_up_to_date(&self, runtime: &Runtime, library: bool) -> bool {
+        let Some(key) = self.program_key(runtime, library) else {
+            return false;
+        };
+        self.output(library).exists() && self.read_key(PROGRAM_KEY) == Some(key)
+    }
+
+    // Writes the C source into the build directory, which is reused if it exists, and builds the
+    // executable, or the library when there is a header. A unit is only compiled again when its C
+    // or the flags changed, and the output only linked again when an object or the link line did.
+    // Failures are reported through the emitter, with what to do about them.
+    pub fn build(&self, source: &Generated, runtime: &Runtime) -> bool {
+        let library = source.header.is_some();
+        if let Err(e) = std::fs::create_dir_all(&self.options.build_dir) {
+            self.output_failed(&self.options.build_dir, e);
+            return false;
+        }
+        // Until this build succeeds, the output may not be that of the program
+        self.remove_key(PROGRAM_KEY);
+        if let Some(header) = &source.header {
+            if self
+                .update(&self.options.unit_path(0, source.header_extension), header)
+                .is_none()
+            {
+                return false;
+            }
+        }
+
+        let objects_key = self.objects_key(runtime);
+        let flags_changed = self.read_key(OBJECTS_KEY) != Some(objects_key);
+        let mut objects = Vec::new();
+        let mut compiled = false;
+        for (unit, code) in source.units.iter().enumerate() {
+            let path = self.options.unit_path(unit, source.extension);
+            let object = path.with_extension("o");
+            let Some(changed) = self.update(&path, code) else {
+                return false;
+            };
+            if changed || flags_changed || !object.exists() {
+                // A failed compilation must not leave the previous object to be reused
+                let _ = std::fs::remove_file(&object);
+                if !self.compile(&path, &object, runtime) {
+                    return false;
+                }
+                compiled = true;
+            }
+            objects.push(object);
+        }
+        if flags_changed && !self.write_key(OBJECTS_KEY, objects_key) {
+            return false;
+        }
+
+        let output_key = fnv1a(
+            self.link_key(runtime, library),
+            &objects.len().to_le_bytes(),
+        );
+        if compiled
+            || !self.output(library).exists()
+            || self.read_key(OUTPUT_KEY) != Some(output_key)
+        {
+            self.remove_key(OUTPUT_KEY);
+            let linked = if library {
+                self.archive(&objects, runtime)
+            } else {
+                self.link(&objects, runtime)
+            };
+            if !linked || !self.write_key(OUTPUT_KEY, output_key) {
+                return false;
+            }
+        }
+        match self.program_key(runtime, library) {
+            Some(key) => self.write_key(PROGRAM_KEY, key),
+            None => true,
+        }
+    }
+
+    // Where the executable or the library is built
+    pub fn output(&self, library: bool) -> PathBuf {
+        if library {
+            self.options.lib_path()
+        } else {
+            self.options.exe_path()
+        }
+    }
+
+    fn output_failed(&self, path: &Path, e: std::io::Error) {
+        self.emitter.emit(Diagnostic::error(
+            Code::OutputFailed,
+            None,
+            format!("Failed to write {}: {}", path.display(), e),
+        ));
+    }
+
+    // Writes a file unless it already has the contents, and tells whether it did, so that what
+    // is built from it is kept otherwise
+    fn update(&self, path: &Path, contents: &str) -> Option<bool> {
+        if std::fs::read(path).is_ok_and(|old| old == contents.as_bytes()) {
+            return Some(false);
+        }
+        match std::fs::write(path, contents) {
+            Ok(()) => Some(true),
+            Err(e) => {
+                self.output_failed(path, e);
+                None
+            }
+        }
+    }
+
+    // What the objects are compiled with: the C compiler, the flags and the runtime's headers
+    fn objects_key(&self, runtime: &Runtime) -> u64 {
+        let mut hash = fnv1a(FNV_OFFSET, self.options.cc.as_bytes());
+        hash = fnv1a(hash, self.options.cflags().join("\0").as_bytes());
+        hash = fnv1a(hash, runtime.include.as_os_str().as_encoded_bytes());
+        for (name, _) in HEADERS {
+            hash = file_stamp(hash, &runtime.include.join(name));
+        }
+        hash
+    }
+
+    // What the output is built from besides the objects: the link line and the files on it
+    fn link_key(&self, runtime: &Runtime, library: bool) -> u64 {
+        let mut hash = fnv1a(self.objects_key(runtime), &[library as u8]);
+        hash = fnv1a(hash, self.output(library).as_os_str().as_encoded_bytes());
+        for arg in self.options.link.iter().chain(&self.options.ldflags) {
+            hash = fnv1a(hash, arg.as_bytes());
+            hash = fnv1a(hash, &[0]);
+            hash = file_stamp(hash, Path::new(arg));
+        }
+        file_stamp(hash, &runtime.lib.join("libic.a"))
+    }
+
+    // What the output is, for a program of a known fingerprint
+    fn program_key(&self, runtime: &Runtime, library: bool) -> Option<u64> {
+        let fingerprint = self.options.fingerprint?;
+        Some(fnv1a(
+            self.link_key(runtime, library),
+            &fingerprint.to_le_bytes(),
+        ))
+    }
+
+    fn read_key(&self, name: &str) -> Option<u64> {
+        let key = std::fs::read_to_string(self.options.build_dir.join(name)).ok()?;
+        u64::from_str_radix(key.trim(), 16).ok()
+    }
+
+    fn write_key(&self, name: &str, key: u64) -> bool {
+        let path = self.options.build_dir.join(name);
+        match std::fs::write(&path, format!("{:016x}\n", key)) {
+            Ok(()) => true,
+            Err(e) => {
+                self.output_failed(&path, e);
+                false
+            }
+        }
+    }
+
+    fn remove_key(&self, name: &str) {
+        let _ = std::fs::remove_file(self.options.build_dir.join(name));
+    }
+
+    fn compile(&self, path: &Path, object: &Path, runtime: &Runtime) -> bool {
+        let mut command = cc_command(&self.options.cc);
+        command
+            .arg("-c")
+            .arg("-o")
+            .arg(object)
+            .arg(path)
+            .args(self.options.cflags())
+            .arg("-I")
+            .arg(&runtime.include);
+        self.run(&mut command)
+    }
+
+    // C sources on the link line are compiled with the program's flags
+    fn link(&self, objects: &[PathBuf], runtime: &Runtime) -> bool {
+        let mut command = cc_command(&self.options.cc);
+        command
+            .arg("-o")
+            .arg(self.options.exe_path())
+            .args(objects)
+            .args(self.options.link.iter())
+            .args(self.options.cflags())
+            .arg("-I")
+            .arg(&runtime.include)
+            .arg("-L")
+            .arg(&runtime.lib)
+            .arg("-l")
+            .arg("ic")
+            .args(self.options.ldflags.iter());
+        self.run(&mut command)
+    }
+
+    // C sources and objects on the link line, such as the definitions of externs, go into the
+    // library with the program's objects. The runtime's library and headers are copied next to it,
+    // to build against them.
+    fn archive(&self, objects: &[PathBuf], runtime: &Runtime) -> bool {
+        let mut members = objects.to_vec();
+        for (i, input) in self.options.link.iter().enumerate() {
+            if input.ends_with(".c") {
+                let object = self.options.build_dir.join(format!("link{}.o", i));
+                if !self.compile(Path::new(input), &object, runtime) {
+                    return false;
+                }
+                members.push(object);
+            } else if input.ends_with(".o") {
+                members.push(PathBuf::from(input));
+            } else {
+                self.emitter.emit(
+                    Diagnostic::error(
+                        Code::LibraryLinkInput,
+                        None,
+                        format!("{} can't be put into a library", input),
+                    )
+                    .with_help("pass libraries and link flags when linking the program that uses the library"),
+                );
+                return false;
+            }
+        }
+
+        // ar adds to an existing archive, which may have the objects of units that are gone
+        let _ = std::fs::remove_file(self.options.lib_path());
+        if !self.run(
+            Command::new("ar")
+                .arg("rcs")
+                .arg(self.options.lib_path())
+                .args(&members),
+        ) {
+            return false;
+        }
+
+        let copies = HEADERS
+            .iter()
+            .map(|(name, _)| {
+                (
+                    runtime.include.join(name),
+                    self.options.build_dir.join(name),
+                )
+            })
+            .chain(std::iter::once((
+                runtime.lib.join("libic.a"),
+                self.options.build_dir.join("libic.a"),
+            )));
+        for (from, to) in copies {
+            if let Err(e) = std::fs::copy(&from, &to) {
+                self.emitter.emit(Diagnostic::error(
+                    Code::OutputFailed,
+                    None,
+                    format!(
+                        "Failed to copy {} to {}: {}",
+                        from.display(),
+                        to.display(),
+                        e
+                    ),
+                ));
+                return false;
+            }
+        }
+        true
+    }
+
+    // Runs a tool to completion. Its diagnostics are passed on, and a failure is explained.
+    fn run(&self, command: &mut Command) -> bool {
+        let tool = command.get_program().to_string_lossy().into_owned();
+        let output = match command.output() {
+            Ok(output) => output,
+            Err(e) => {
+                let mut diagnostic = Diagnostic::error(
+                    Code::ToolUnavailable,
+                    None,
+                    format!("Failed to run {}: {}", tool, e),
+                );
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    diagnostic =
+                        diagnostic.with_help(format!("install {}, or add it to PATH", tool));
+                }
+                self.emitter.emit(diagnostic);
+                return false;
+            }
+        };
+
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        stderr
+            .lines()
+            .filter_map(cc_diagnostic)
+            .for_each(|diagnostic| self.emitter.emit(diagnostic));
+        if output.status.success() {
+            return true;
+        }
+
+        let mut undefined = stderr
+            .lines()
+            .filter_map(|line| line.split_once("undefined reference to `"))
+            .map(|(_, symbol)| symbol.trim_end_matches('\''))
+            .collect::<Vec<_>>();
+        undefined.sort();
+        undefined.dedup();
+        if undefined.is_empty() {
+            self.emitter.emit(Diagnostic::error(
+                Code::ToolFailed,
+                None,
+                format!("{} failed ({})", tool, output.status),
+            ));
+        } else {
+            self.emitter.emit(
+                Diagnostic::error(
+                    Code::UndefinedSymbol,
+                    None,
+                    format!("C functions used by the program are not defined: {}", undefined.join(", ")),
+                )
+                .with_help("pass the C sources, objects or libraries that define them after the program, as in 'ic prog.fl hash.c'"),
+            );
+        }
+        false
+    }
+}
+
+// Adds a file's size and modification time to a hash, as make goes by the latter. Files that
+// don't exist add nothing.
+pub fn file_stamp(hash: u64, path: &Path) -> u64 {
+    let Ok(metadata) = std::fs::metadata(path) else {
+        return hash;
+    };
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map_or(0, |time| time.as_nanos());
+    let hash = fnv1a(hash, &metadata.len().to_le_bytes());
+    fnv1a(hash, &modified.to_le_bytes())
+}
+
+// Parses a diagnostic line of gcc or the linker: `file:line:col: severity: message`,
+// `tool: severity: message` or `object:(section): message`.
+// Context lines, like source excerpts and "In function" headers, are skipped.
+fn cc_diagnostic(line: &str) -> Option<Diagnostic> {
+    fn severity(rest: &str) -> Option<(Severity, &str)> {
+        [
+            ("fatal error: ", Severity::Error),
+            ("error: ", Severity::Error),
+            ("warning: ", Severity::Warning),
+            ("note: ", Severity::Note),
+        ]
+        .into_iter()
+        .find_map(|(prefix, severity)| rest.strip_prefix(prefix).map(|message| (severity, message)))
+    }
+
+    let parts = line.splitn(4, ':').collect::<Vec<_>>();
+    if let [file, line, col, rest] = parts[..] {
+        if let (Ok(line), Ok(col)) = (line.parse(), col.parse()) {
+            let (severity, message) = severity(rest.trim_start())?;
+            let loc = Loc { line, col };
+            return Some(Diagnostic::external(
+                severity,
+                Some(file.to_string()),
+                Some(Span::new(loc, loc)),
+                message,
+            ));
+        }
+    }
+
+    let (file, rest) = line.split_once(": ")?;
+    if let Some((severity, message)) = severity(rest) {
+        return Some(Diagnostic::external(
+            severity,
+            Some(file.to_string()),
+            None,
+            message,
+        ));
+    }
+    if rest.starts_with("undefined reference") || rest.starts_with("multiple definition") {
+        let file = file.split_once(':').map_or(file, |(file, _)| file);
+        return Some(Diagnostic::external(
+            Severity::Error,
+            Some(file.to_string()),
+            None,
+            rest,
+        ));
+    }
+    None
+}
@@ -0,0 +1,258 @@
+use std::path::PathBuf;
+
+use crate::diagnostic::ErrorFormat;
+use crate::lint::Lint;
+
+pub const USAGE: &str = "\
+Usage: ic [options] <file.fl> [C sources, objects and libraries...]
+       ic run [options] <file.fl> [C sources, objects and libraries...] [-- <program arguments>...]
+       ic build [options] [project directory]
+
+Compiles an fl program to an executable, through C. 'ic run' also runs it, with the arguments
+after '--', and exits with its exit status. 'ic build' compiles the project of the closest ic.toml.
+
+Options:
+  -o <path>                Write the output to <path>
+  --build-dir <dir>        Keep intermediate files in <dir> (default: _build, or a cache
+                           directory for 'ic run')
+  --emit=<stage>           Stop after a stage and write its output:
+                           tokens, ast, hir, il, c or exe (default: exe)
+  --emit-lib               Build a static library and its header instead of an executable
+  --interact               Stream 'result' to stdout as a list of character codes
+  --no-contracts           Don't check requires/ensures clauses
+  --release, --debug       Optimise the C code (default), or build it unoptimised with
+                           debug information. With 'ic build', the release or dev profile
+  --profile <name>         The profile of ic.toml that 'ic build' uses (default: dev)
+  -g                       Build the C code with debug information
+  --cc <command>           The C compiler (default: $CC, or gcc)
+  --cflags <flags>         Extra flags to compile the generated C code with
+  --ldflags <flags>        Extra flags for the link line
+  --sanitize=<sanitizers>  Instrument the program and the runtime, as in address,undefined
+  --error-format=<format>  Report diagnostics as human (default) or json
+  -W <lint>, -A <lint>     Enable or silence a lint, or all of them
+  -l<lib>, -L<dir>         Passed on to the C compiler's link line
+  -h, --help               Print this help
+  -V, --version            Print the version
+
+Lints: unreachable-definition, unused-argument, self-recursive-constant, non-boolean-condition
+";
+
+// The stage of the pipeline whose output is written
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Emit {
+    Tokens,
+    Ast,
+    Hir,
+    Il,
+    C,
+    Exe,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    pub path: String,
+    pub emit: Emit,
+    // Where the output of the last stage goes, by default stdout for dumps and the build directory otherwise
+    pub output: Option<PathBuf>,
+    // By default _build, or a directory per program in the cache for 'ic run'
+    pub build_dir: Option<PathBuf>,
+    pub interact: bool,
+    pub emit_lib: bool,
+    pub contracts: bool,
+    pub debug: bool,
+    pub debug_info: bool,
+    pub cc: Option<String>,
+    pub cflags: Vec<String>,
+    pub ldflags: Vec<String>,
+    // Passed on to the C compiler's -fsanitize
+    pub sanitize: Option<String>,
+    pub profile: Option<String>,
+    pub error_format: ErrorFormat,
+    pub lints: Vec<Lint>,
+    // Extra objects and libraries for the C compiler's link line
+    pub link: Vec<String>,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Compile(Args),
+    // Compiles the program and runs it with the arguments
+    Run(Args, Vec<String>),
+    // Compiles the project of the ic.toml in the directory of `path`, or the closest of its parents
+    Build(Args),
+    Help,
+    Version,
+}
+
+pub fn parse(args: impl Iterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.peekable();
+    let run = args.next_if(|arg| arg == "run").is_some();
+    let build = !run && args.next_if(|arg| arg == "build").is_some();
+    let mut program_args = Vec::new();
+
+    let mut path = None;
+    let mut emit = None;
+    let mut output = None;
+    let mut build_dir = None;
+    let mut interact = false;
+    let mut emit_lib = false;
+    let mut contracts = true;
+    let mut debug = false;
+    let mut debug_info = false;
+    let mut cc = None;
+    let mut cflags = Vec::new();
+    let mut ldflags = Vec::new();
+    let mut sanitize = None;
+    let mut profile = None;
+    let mut named_profile = false;
+    let mut error_format = ErrorFormat::Human;
+    let mut lints = Lint::ALL.to_vec();
+    let mut link = Vec::new();
+
+    while let Some(arg) = args.next() {
+        // Options that take a value accept it as the next argument or after '='
+        let (name, inline) = match arg.split_once('=') {
+            Some((name, value)) if name.starts_with("--") => (name, Some(value.to_string())),
+            _ => (arg.as_str(), None),
+        };
+        let mut value = |name: &str| {
+            inline
+                .clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("{} requires a value", name))
+        };
+
+        match name {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-V" | "--version" => return Ok(Command::Version),
+            "-o" => output = Some(PathBuf::from(value(name)?)),
+            "--build-dir" => build_dir = Some(PathBuf::from(value(name)?)),
+            "--emit" => {
+                emit = Some(match value(name)?.as_str() {
+                    "tokens" => Emit::Tokens,
+                    "ast" => Emit::Ast,
+                    "hir" => Emit::Hir,
+                    "il" => Emit::Il,
+                    "c" => Emit::C,
+                    "exe" => Emit::Exe,
+                    stage => return Err(format!("Unknown stage to emit: {}", stage)),
+                })
+            }
+            "--error-format" => {
+                error_format = match value(name)?.as_str() {
+                    "human" => ErrorFormat::Human,
+                    "json" => ErrorFormat::Json,
+                    format => return Err(format!("Unknown error format: {}", format)),
+                }
+            }
+            "--interact" => interact = true,
+            "--emit-lib" => emit_lib = true,
+            "--no-contracts" => contracts = false,
+            "--release" => {
+                debug = false;
+                profile = Some("release".to_string());
+            }
+            "--debug" => {
+                debug = true;
+                profile = Some("dev".to_string());
+            }
+            "--profile" => {
+                profile = Some(value(name)?);
+                named_profile = true;
+            }
+            "-g" => debug_info = true,
+            "--cc" => cc = Some(value(name)?),
+            // Flags are split on whitespace, and add up when repeated
+            "--cflags" => cflags.extend(value(name)?.split_whitespace().map(String::from)),
+            "--ldflags" => ldflags.extend(value(name)?.split_whitespace().map(String::from)),
+            "--sanitize" => {
+                let sanitizers = value(name)?;
+                if sanitizers.is_empty() {
+                    return Err("--sanitize requires a sanitizer".to_string());
+                }
+                sanitize = Some(sanitizers);
+            }
+            // The rest of the arguments are the program's
+            "--" if run => {
+                program_args.extend(args.by_ref());
+            }
+            // Enables or silences a lint, as '-Wname', '-W name', or 'all' of them
+            _ if arg.starts_with("-W") || arg.starts_with("-A") => {
+                let name = match &arg[2..] {
+                    "" => value(&arg[..2])?,
+                    name => name.to_string(),
+                };
+                let selected = if name == "all" {
+                    Lint::ALL.to_vec()
+                } else if let Some(lint) = Lint::from_name(&name) {
+                    vec![lint]
+                } else {
+                    return Err(format!("Unknown lint: {}", name));
+                };
+                lints.retain(|lint| !selected.contains(lint));
+                if arg.starts_with("-W") {
+                    lints.extend(selected);
+                }
+            }
+            _ if arg.starts_with("-l") || arg.starts_with("-L") => link.push(arg),
+            _ if [".c", ".o", ".a", ".so"]
+                .iter()
+                .any(|ext| arg.ends_with(ext)) =>
+            {
+                link.push(arg)
+            }
+            _ if arg.starts_with('-') => return Err(format!("Unknown option: {}", arg)),
+            _ if path.is_none() => path = Some(arg),
+            _ => return Err(format!("Unexpected argument: {}", arg)),
+        }
+    }
+
+    let path = match path {
+        Some(path) => path,
+        None if build => ".".to_string(),
+        None => return Err("Source file required".to_string()),
+    };
+    if !build && named_profile {
+        return Err("--profile can only be used with 'ic build'".to_string());
+    }
+
+    if interact && emit_lib {
+        return Err("--interact can't be used with --emit-lib".to_string());
+    }
+    if build && emit_lib {
+        return Err("'ic build' builds a library when ic.toml says kind = \"lib\"".to_string());
+    }
+    if run && emit_lib {
+        return Err("'ic run' can't be used with --emit-lib".to_string());
+    }
+    if run && emit.is_some_and(|emit| emit != Emit::Exe) {
+        return Err("'ic run' can't be used with --emit".to_string());
+    }
+
+    let args = Args {
+        path,
+        emit: emit.unwrap_or(Emit::Exe),
+        output,
+        build_dir,
+        interact,
+        emit_lib,
+        contracts,
+        debug,
+        debug_info,
+        cc,
+        cflags,
+        ldflags,
+        sanitize,
+        profile,
+        error_format,
+        lints,
+        link,
+    };
+    Ok(if run {
+        Command::Run(args, program_args)
+    } else if build {
+        Command::Build(args)
+    } else {
+        Command::Compile(args)
+    })
+}
@@ -0,0 +1,597 @@
+use std::cell::{Cell, RefCell};
+use std::fmt::Display;
+use std::io::IsTerminal;
+
+use crate::loc::{self, Span};
+
+// Codes are stable: a code is never renumbered or reused for a different error,
+// new errors get new numbers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Code {
+    // Lexing
+    UnexpectedCharacter = 1,
+    InvalidAtom = 2,
+    UnterminatedString = 3,
+
+    // Parsing
+    UnexpectedToken = 100,
+    UnknownAnnotation = 101,
+    DuplicateArgument = 102,
+
+    // Semantic analysis
+    UndefinedVariable = 200,
+    UndefinedFunction = 201,
+    ArityMismatch = 202,
+    DuplicateDefinition = 203,
+    MissingResult = 204,
+    ShadowedGlobal = 205,
+    ExternClash = 206,
+    ArrayFunctionArity = 207,
+    DuplicateField = 208,
+    UnknownField = 209,
+    TooManyArguments = 210,
+    TooManyFields = 211,
+
+    // Lints, which are warnings
+    UnreachableDefinition = 300,
+    UnusedArgument = 301,
+    SelfRecursiveConstant = 302,
+    NonBooleanCondition = 303,
+
+    // Building with the C compiler
+    RuntimeNotFound = 400,
+    RuntimeNotBuilt = 401,
+    ToolUnavailable = 402,
+    ToolFailed = 403,
+    UndefinedSymbol = 404,
+    RuntimeBuildFailed = 405,
+    OutputFailed = 406,
+    LibraryLinkInput = 407,
+}
+
+impl Display for Code {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "E{:04}", *self as u16)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Severity {
+    Error,
+    Warning,
+    Note,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Note => write!(f, "note"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ErrorFormat {
+    Human,
+    // One JSON object per diagnostic and line
+    Json,
+}
+
+#[derive(Clone, Debug)]
+enum Note {
+    Note(String),
+    Help(String),
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    severity: Severity,
+    // Diagnostics of other tools, like the C compiler, have no code
+    code: Option<Code>,
+    message: String,
+    // Set for diagnostics about files other than the source, which are shown without a snippet
+    file: Option<String>,
+    // Errors about the program as a whole, like a missing 'result', have no span
+    span: Option<Span>,
+    // Secondary spans, such as a previous definition
+    labels: Vec<(Span, String)>,
+    notes: Vec<Note>,
+}
+
+impl Diagnostic {
+    pub fn error<S: Into<String>>(code: Code, span: Option<Span>, message: S) -> Self {
+        Self {
+            severity: Severity::Error,
+            code: Some(code),
+            message: message.into(),
+            file: None,
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn warning<S: Into<String>>(code: Code, span: Option<Span>, message: S) -> Self {
+        Self {
+            severity: Severity::Warning,
+            ..Self::error(code, span, message)
+        }
+    }
+
+    // A diagnostic reported by another tool about one of its files
+    pub fn external<S: Into<String>>(
+        severity: Severity,
+        file: Option<String>,
+        span: Option<Span>,
+        message: S,
+    ) -> Self {
+        Self {
+            severity,
+            code: None,
+            message: message.into(),
+            file,
+            span,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn with_label<S: Into<String>>(mut self, span: Span, label: S) -> Self {
+        self.labels.push((span, label.into()));
+        self
+    }
+
+    pub fn with_note<S: Into<String>>(mut self, note: S) -> Self {
+        self.notes.push(Note::Note(note.into()));
+        self
+    }
+
+    pub fn with_help<S: Into<String>>(mut self, help: S) -> Self {
+        self.notes.push(Note::Help(help.into()));
+        self
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.severity
+    }
+
+    pub fn code(&self) -> Option<Code> {
+        self.code
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    // The file the diagnostic is about, when it isn't the source
+    pub fn file(&self) -> Option<&str> {
+        self.file.as_deref()
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+}
+
+// The diagnostics of a compilation, with the source they refer to so that they can be rendered
+#[derive(Clone, Debug)]
+pub struct Diagnostics {
+    path: String,
+    source: String,
+    files: Vec<(String, usize)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Diagnostics {
+    pub fn iter(&self) -> impl Iterator<Item = &Diagnostic> {
+        self.diagnostics.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.diagnostics.is_empty()
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .count()
+    }
+
+    pub fn extend(&mut self, other: Diagnostics) {
+        self.diagnostics.extend(other.diagnostics);
+    }
+
+    fn files(&self) -> Vec<(&str, usize)> {
+        self.files
+            .iter()
+            .map(|(file, before)| (file.as_str(), *before))
+            .collect()
+    }
+
+    // Writes the diagnostics to stderr, closed by a summary when some are errors
+    pub fn report(&self, format: ErrorFormat) {
+        let emitter = Emitter::new(&self.path, &self.source, format).with_files(&self.files());
+        self.iter().for_each(|diagnostic| emitter.print(diagnostic));
+        if emitter.error_count() > 0 {
+            emitter.summary();
+        }
+    }
+}
+
+// Renders the diagnostics for humans, without colors
+impl Display for Diagnostics {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let emitter = Emitter::collecting(&self.path, &self.source).with_files(&self.files());
+        self.iter()
+            .try_for_each(|diagnostic| write!(f, "{}", emitter.render(diagnostic)))
+    }
+}
+
+impl std::error::Error for Diagnostics {}
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// Renders diagnostics against the source they refer to, or collects them, and counts them.
+pub struct Emitter<'src> {
+    path: &'src str,
+    source: &'src str,
+    lines: Vec<&'src str>,
+    // The files of a program made of several, as for loc::locate
+    files: Vec<(&'src str, usize)>,
+    format: ErrorFormat,
+    color: bool,
+    errors: Cell<usize>,
+    // Set when the diagnostics are kept for the caller instead of written to stderr
+    collected: Option<RefCell<Vec<Diagnostic>>>,
+}
+
+impl<'src> Emitter<'src> {
+    pub fn new(path: &'src str, source: &'src str, format: ErrorFormat) -> Self {
+        Self {
+            path,
+            source,
+            lines: source.lines().collect(),
+            files: Vec::new(),
+            format,
+            // Keyed on stderr, which the diagnostics are written to, so that redirecting them to a
+            // file gives plain text
+            color: format == ErrorFormat::Human
+                && std::io::stderr().is_terminal()
+                && std::env::var_os("NO_COLOR").is_none(),
+            errors: Cell::new(0),
+            collected: None,
+        }
+    }
+
+    pub fn collecting(path: &'src str, source: &'src str) -> Self {
+        Self {
+            color: false,
+            collected: Some(RefCell::new(Vec::new())),
+            ..Self::new(path, source, ErrorFormat::Human)
+        }
+    }
+
+    pub fn with_files(mut self, files: &[(&'src str, usize)]) -> Self {
+        self.files = files.to_vec();
+        self
+    }
+
+    // The file a span is in, and the span within it
+    fn locate(&self, span: Span) -> (&'src str, Span) {
+        loc::locate(self.path, &self.files, span)
+    }
+
+    // The collected diagnostics, none if they were written to stderr
+    pub fn into_diagnostics(self) -> Diagnostics {
+        Diagnostics {
+            path: self.path.to_string(),
+            source: self.source.to_string(),
+            files: self
+                .files
+                .iter()
+                .map(|(file, before)| (file.to_string(), *before))
+                .collect(),
+            diagnostics: self.collected.map(RefCell::into_inner).unwrap_or_default(),
+        }
+    }
+
+    pub fn error_count(&self) -> usize {
+        self.errors.get()
+    }
+
+    // Closes the report of a failed compilation, tools reading JSON count the diagnostics themselves
+    pub fn summary(&self) {
+        if self.format == ErrorFormat::Json {
+            return;
+        }
+        let count = self.errors.get();
+        eprintln!(
+            "{}error{}{}: could not compile {} due to {} previous error{}{}",
+            self.paint(RED),
+            self.paint(RESET),
+            self.paint(BOLD),
+            self.path,
+            count,
+            if count == 1 { "" } else { "s" },
+            self.paint(RESET)
+        );
+    }
+
+    pub fn emit(&self, diagnostic: Diagnostic) {
+        match &self.collected {
+            Some(collected) => {
+                if diagnostic.severity == Severity::Error {
+                    self.errors.set(self.errors.get() + 1);
+                }
+                collected.borrow_mut().push(diagnostic);
+            }
+            None => self.print(&diagnostic),
+        }
+    }
+
+    fn print(&self, diagnostic: &Diagnostic) {
+        if diagnostic.severity == Severity::Error {
+            self.errors.set(self.errors.get() + 1);
+        }
+        match self.format {
+            ErrorFormat::Human => eprint!("{}", self.render(diagnostic)),
+            ErrorFormat::Json => eprintln!("{}", self.render_json(diagnostic)),
+        }
+    }
+
+    fn render(&self, diagnostic: &Diagnostic) -> String {
+        let color = match diagnostic.severity {
+            Severity::Error => RED,
+            Severity::Warning => YELLOW,
+            Severity::Note => BOLD,
+        };
+        let code = match diagnostic.code {
+            Some(code) => format!("[{}]", code),
+            None => String::new(),
+        };
+        let mut out = format!(
+            "{}{}{}{}{}: {}{}\n",
+            self.paint(color),
+            diagnostic.severity,
+            code,
+            self.paint(RESET),
+            self.paint(BOLD),
+            diagnostic.message,
+            self.paint(RESET)
+        );
+
+        let width = diagnostic
+            .span
+            .iter()
+            .chain(diagnostic.labels.iter().map(|(span, _)| span))
+            .map(|span| self.locate(*span).1.start.line.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = format!(
+            "{}{} |{}",
+            self.paint(BLUE),
+            " ".repeat(width),
+            self.paint(RESET)
+        );
+
+        let path = diagnostic.file.as_deref().unwrap_or(self.path);
+        match diagnostic.span {
+            Some(span) if diagnostic.file.is_some() => {
+                out.push_str(&format!(
+                    "{}{}-->{} {}:{}\n",
+                    " ".repeat(width),
+                    self.paint(BLUE),
+                    self.paint(RESET),
+                    path,
+                    span
+                ));
+            }
+            Some(span) => {
+                let (path, local) = self.locate(span);
+                out.push_str(&format!(
+                    "{}{}-->{} {}:{}\n",
+                    " ".repeat(width),
+                    self.paint(BLUE),
+                    self.paint(RESET),
+                    path,
+                    local
+                ));
+                out.push_str(&format!("{}\n", gutter));
+                out.push_str(&self.snippet(span, '^', "", color, width));
+            }
+            None => {
+                out.push_str(&format!(
+                    "{}{}-->{} {}\n",
+                    " ".repeat(width),
+                    self.paint(BLUE),
+                    self.paint(RESET),
+                    path
+                ));
+            }
+        }
+
+        // Labels in another file than the diagnostic's say which
+        let file = diagnostic.span.map(|span| self.locate(span).0);
+        for (span, label) in diagnostic.labels.iter() {
+            let (path, local) = self.locate(*span);
+            if file.is_some_and(|file| file != path) {
+                out.push_str(&format!(
+                    "{}{}:::{} {}:{}\n",
+                    " ".repeat(width),
+                    self.paint(BLUE),
+                    self.paint(RESET),
+                    path,
+                    local
+                ));
+            }
+            out.push_str(&format!("{}\n", gutter));
+            out.push_str(&self.snippet(*span, '-', label, BLUE, width));
+        }
+
+        for note in diagnostic.notes.iter() {
+            let (kind, text) = match note {
+                Note::Note(text) => ("note", text),
+                Note::Help(text) => ("help", text),
+            };
+            out.push_str(&format!(
+                "{}{} ={} {}{}:{} {}\n",
+                self.paint(BLUE),
+                " ".repeat(width),
+                self.paint(RESET),
+                self.paint(BOLD),
+                kind,
+                self.paint(RESET),
+                text
+            ));
+        }
+        out.push('\n');
+        out
+    }
+
+    fn render_json(&self, diagnostic: &Diagnostic) -> String {
+        let (path, span) = match (&diagnostic.file, diagnostic.span) {
+            (Some(file), span) => (file.as_str(), span),
+            (None, Some(span)) => {
+                let (path, span) = self.locate(span);
+                (path, Some(span))
+            }
+            (None, None) => (self.path, None),
+        };
+        let code = match diagnostic.code {
+            Some(code) => json_string(&code.to_string()),
+            None => "null".to_string(),
+        };
+        let related = diagnostic
+            .labels
+            .iter()
+            .map(|(span, label)| {
+                let (path, span) = self.locate(*span);
+                format!(
+                    "{{\"file\":{},\"span\":{},\"message\":{}}}",
+                    json_string(path),
+                    json_span(Some(span)),
+                    json_string(label)
+                )
+            })
+            .collect::<Vec<_>>();
+        let notes = diagnostic
+            .notes
+            .iter()
+            .map(|note| {
+                let (kind, text) = match note {
+                    Note::Note(text) => ("note", text),
+                    Note::Help(text) => ("help", text),
+                };
+                format!(
+                    "{{\"kind\":\"{}\",\"message\":{}}}",
+                    kind,
+                    json_string(text)
+                )
+            })
+            .collect::<Vec<_>>();
+        format!(
+            "{{\"severity\":\"{}\",\"code\":{},\"message\":{},\"file\":{},\"span\":{},\"related\":[{}],\"notes\":[{}]}}",
+            diagnostic.severity,
+            code,
+            json_string(&diagnostic.message),
+            json_string(path),
+            json_span(span),
+            related.join(","),
+            notes.join(",")
+        )
+    }
+
+    // The line a span starts on, underlined from the span's start to its end,
+    // or to the end of the line if the span continues past it.
+    fn snippet(
+        &self,
+        span: Span,
+        mark: char,
+        label: &str,
+        color: &'static str,
+        width: usize,
+    ) -> String {
+        let line = self
+            .lines
+            .get(span.start.line.saturating_sub(1))
+            .copied()
+            .unwrap_or("");
+        let number = self.locate(span).1.start.line;
+        let start = span.start.col.saturating_sub(1);
+        let len = if span.end.line == span.start.line {
+            span.end.col.saturating_sub(span.start.col)
+        } else {
+            line.chars().count().saturating_sub(start)
+        };
+
+        // Tabs are kept so that the underline lines up however they are displayed
+        let indent = line
+            .chars()
+            .take(start)
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect::<String>();
+        let underline = mark.to_string().repeat(len.max(1));
+        let label = if label.is_empty() {
+            String::new()
+        } else {
+            format!(" {}", label)
+        };
+
+        format!(
+            "{}{:>width$} |{} {}\n{}{} |{} {}{}{}{}{}\n",
+            self.paint(BLUE),
+            number,
+            self.paint(RESET),
+            line,
+            self.paint(BLUE),
+            " ".repeat(width),
+            self.paint(RESET),
+            indent,
+            self.paint(color),
+            underline,
+            label,
+            self.paint(RESET),
+            width = width
+        )
+    }
+
+    fn paint(&self, code: &'static str) -> &'static str {
+        if self.color {
+            code
+        } else {
+            ""
+        }
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut out = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+fn json_span(span: Option<Span>) -> String {
+    match span {
+        Some(span) => format!(
+            "{{\"start\":{{\"line\":{},\"col\":{}}},\"end\":{{\"line\":{},\"col\":{}}}}}",
+            span.start.line, span.start.col, span.end.line, span.end.col
+        ),
+        None => "null".to_string(),
+    }
+}
@@ -1,23 +1,65 @@
+use crate::loc::Span;
+
 pub type Program<'src> = Box<[Definition<'src>]>;
 
 #[derive(Debug)]
 pub struct Definition<'src> {
     pub name: &'src str,
+    // From the name, or 'extern', to the end of the body
+    pub span: Span,
     pub args: Box<[&'src str]>,
+    // The span of each argument's name
+    pub arg_spans: Box<[Span]>,
     pub body: Expr<'src>,
+    // Contract clauses, the postcondition refers to the returned value as 'result'
+    pub requires: Option<Expr<'src>>,
+    pub ensures: Option<Expr<'src>>,
+    // Annotated with @memo
+    pub memo: bool,
 }
 
 impl<'src> Definition<'src> {
-    pub fn new(name: &'src str, args: Box<[&'src str]>, body: Expr<'src>) -> Self {
-        Self { name, args, body }
+    pub fn new(
+        name: &'src str,
+        span: Span,
+        args: Box<[&'src str]>,
+        arg_spans: Box<[Span]>,
+        body: Expr<'src>,
+        requires: Option<Expr<'src>>,
+        ensures: Option<Expr<'src>>,
+    ) -> Self {
+        Self {
+            name,
+            span,
+            args,
+            arg_spans,
+            body,
+            requires,
+            ensures,
+            memo: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Expr<'src> {
+    pub kind: ExprKind<'src>,
+    pub span: Span,
+}
+
+impl<'src> Expr<'src> {
+    pub fn new(kind: ExprKind<'src>, span: Span) -> Self {
+        Self { kind, span }
     }
 }
 
 #[derive(Debug)]
-pub enum Expr<'src> {
+pub enum ExprKind<'src> {
     Var(&'src str),
     Atom(&'src str),
     Num(i64),
+    // The C function to call, and which of the arguments are passed lazily
+    Extern(&'src str, Box<[bool]>),
     Add(Box<Expr<'src>>, Box<Expr<'src>>),
     Sub(Box<Expr<'src>>, Box<Expr<'src>>),
     Mul(Box<Expr<'src>>, Box<Expr<'src>>),
@@ -33,4 +75,8 @@ pub enum Expr<'src> {
     Cons(Box<Expr<'src>>, Box<Expr<'src>>),
     Car(Box<Expr<'src>>),
     Cdr(Box<Expr<'src>>),
+    Record(Box<[(&'src str, Expr<'src>)]>),
+    Field(Box<Expr<'src>>, &'src str),
+    // The record, and the fields that are replaced in its copy
+    Update(Box<Expr<'src>>, Box<[(&'src str, Expr<'src>)]>),
 }
@@ -1,18 +1,50 @@
 use std::collections::HashMap;
 
+use crate::diagnostic::{Code, Diagnostic, Emitter};
 use crate::fl;
 use crate::hir;
+use crate::itoc;
+use crate::loc::Span;
 
-pub struct FtoH<'src> {
+// The builtin stream of stdin's characters, and the builtin value read at runtime, which a
+// definition or argument of the same name hides
+const INPUT: &str = "input";
+const READ_VALUE: &str = "readValue";
+
+// The builtin functions on arrays, which definitions of the same name hide as well
+const ARRAY: &str = "array";
+const AT: &str = "at";
+const LENGTH: &str = "length";
+
+// A LAR counts its arguments in a byte, and a postcondition's 'result' takes one more
+const MAX_ARGUMENTS: usize = u8::MAX as usize;
+// Records count their fields in a byte as well
+const MAX_FIELDS: usize = u8::MAX as usize;
+
+// Prefixes of the C names of library entry points, generated code and the runtime, which externs
+// can't bind to
+const RESERVED_PREFIXES: [&str; 3] = [itoc::LIB_PREFIX, "__", "IC_"];
+
+pub struct FtoH<'src, 'e> {
     pub program: fl::Program<'src>,
+    emitter: &'e Emitter<'e>,
     // Maps global variables to their index in the program definitions array.
     pub globals: HashMap<&'src str, usize>,
     // Maps function definition indexes to the number of times they have been called.
     pub func_calls: HashMap<usize, usize>,
     pub cons_calls: usize,
+    // The number of record fields, in literals and updates, converted so far.
+    pub field_calls: usize,
+    pub shapes: Vec<Box<[&'src str]>>,
+    // Fields that are accessed or updated, which have to be in some record literal
+    used_fields: Vec<(&'src str, Span)>,
     pub var_indices: HashMap<String, usize>,
     pub atoms_map: HashMap<&'src str, usize>,
     pub atom_names: Vec<&'src str>,
+    // Libraries are entered through their exported definitions instead of 'result'
+    pub require_result: bool,
+    // Whether 'result' refers to the returned value, in a postcondition
+    in_ensures: bool,
 }
 
 macro_rules! obf_var {
@@ -21,29 +53,40 @@ macro_rules! obf_var {
     };
 }
 
-impl<'src> FtoH<'src> {
-    pub fn new(program: fl::Program<'src>) -> Self {
+impl<'src, 'e> FtoH<'src, 'e> {
+    pub fn new(program: fl::Program<'src>, emitter: &'e Emitter<'e>, require_result: bool) -> Self {
         Self {
             program,
+            emitter,
+            require_result,
+            in_ensures: false,
             atoms_map: HashMap::from([("nil", 0), ("true", 1), ("false", 2)]),
             atom_names: vec!["nil", "true", "false"],
             globals: HashMap::new(),
             func_calls: HashMap::new(),
             var_indices: HashMap::new(),
             cons_calls: 0,
+            field_calls: 0,
+            shapes: Vec::new(),
+            used_fields: Vec::new(),
         }
     }
 
+    // Every error is reported, the program is only returned if there were none
     pub fn convert(mut self) -> Option<hir::Program<'src>> {
-        self.check_for_result()?;
+        let errors = self.emitter.error_count();
+        if self.require_result {
+            self.check_for_result();
+        }
 
-        self.check_duplicate_definitions()?;
+        self.check_duplicate_definitions();
 
         let old_definitions = std::mem::replace(&mut self.program, Box::new([]));
 
         let mut definitions = Vec::new();
-        for def in old_definitions.into_iter() {
+        for def in old_definitions.iter() {
             let name = def.name;
+            self.check_argument_count(def);
             let args = def
                 .args
                 .iter()
@@ -52,40 +95,183 @@ impl<'src> FtoH<'src> {
             args.iter().enumerate().for_each(|(i, arg)| {
                 self.var_indices.insert(arg.clone(), i);
             });
-            let body = self.convert_body(&old_definitions, &def, &def.body)?;
-            definitions.push(hir::Definition::new(name, args, body));
+            let body = self.convert_body(&old_definitions, def, &def.body);
+
+            let requires = def
+                .requires
+                .as_ref()
+                .map(|requires| self.convert_body(&old_definitions, def, requires));
+
+            let ensures = def.ensures.as_ref().map(|ensures| {
+                self.var_indices
+                    .insert(obf_var!(def.name, "result"), def.args.len());
+                self.in_ensures = true;
+                let ensures = self.convert_body(&old_definitions, def, ensures);
+                self.in_ensures = false;
+                ensures
+            });
+
+            let mut definition = hir::Definition::new(
+                name,
+                def.span,
+                args,
+                def.arg_spans.clone(),
+                body,
+                requires,
+                ensures,
+            );
+            definition.memo = def.memo;
+            definitions.push(definition);
+        }
+
+        self.check_used_fields();
+
+        if self.emitter.error_count() > errors {
+            return None;
         }
 
         Some(hir::Program::new(
             definitions.into_boxed_slice(),
             self.var_indices,
             self.atom_names.into_boxed_slice(),
+            self.shapes.into_boxed_slice(),
         ))
     }
 
-    fn check_for_result(&self) -> Option<()> {
+    fn check_argument_count(&self, def: &fl::Definition<'src>) {
+        let count = def.args.len() + usize::from(def.ensures.is_some());
+        if count > MAX_ARGUMENTS {
+            self.emitter.emit(
+                Diagnostic::error(
+                    Code::TooManyArguments,
+                    Some(def.span),
+                    format!("'{}' has too many arguments", def.name),
+                )
+                .with_help(format!(
+                    "a definition takes at most {MAX_ARGUMENTS} arguments, counting 'result' in a postcondition"
+                )),
+            );
+        }
+    }
+
+    fn check_for_result(&self) {
         for def in self.program.iter() {
             if def.name == "result" {
-                return Some(());
+                return;
             }
         }
 
-        self.error("Exactly one 'result' nullary function must be defined");
-        None
+        self.emitter.emit(
+            Diagnostic::error(
+                Code::MissingResult,
+                None,
+                "Exactly one 'result' nullary function must be defined",
+            )
+            .with_help("the program's value is that of 'result', as in 'result = ...'"),
+        );
     }
 
-    fn check_duplicate_definitions(&mut self) -> Option<()> {
+    // Later definitions of a name are reported, and the first one is kept
+    fn check_duplicate_definitions(&mut self) {
         for (i, def) in self.program.iter().enumerate() {
             if self.globals.contains_key(def.name) {
-                self.error(format!("Duplicate definition: {}", def.name));
-                return None;
+                let previous = &self.program[self.globals[def.name]];
+                self.emitter.emit(
+                    Diagnostic::error(
+                        Code::DuplicateDefinition,
+                        Some(def.span),
+                        format!("Duplicate definition: {}", def.name),
+                    )
+                    .with_label(previous.span, "previous definition here"),
+                );
+                continue;
             }
             self.globals.insert(def.name, i);
-            if def.args.len() > 0 {
+            if !def.args.is_empty() {
                 self.func_calls.insert(i, 0);
             }
         }
-        Some(())
+    }
+
+    fn check_used_fields(&self) {
+        for (field, span) in self.used_fields.iter() {
+            if !self.shapes.iter().any(|shape| shape.contains(field)) {
+                self.emitter.emit(
+                    Diagnostic::error(
+                        Code::UnknownField,
+                        Some(*span),
+                        format!("Unknown field: {}", field),
+                    )
+                    .with_note(
+                        "fields are introduced by record literals, and no literal has this one",
+                    ),
+                );
+            }
+        }
+    }
+
+    // The fields of a record whose shape is known at compile time: a literal, an update of one, or a
+    // global defined as one. `seen` holds the globals followed so far, which may refer to each other.
+    fn known_fields(
+        &self,
+        definitions: &fl::Program<'src>,
+        def: &fl::Definition<'src>,
+        record: &fl::Expr<'src>,
+        seen: &mut Vec<usize>,
+    ) -> Option<Vec<&'src str>> {
+        match &record.kind {
+            fl::ExprKind::Record(fields) => Some(fields.iter().map(|(name, _)| *name).collect()),
+            fl::ExprKind::Update(record, _) => self.known_fields(definitions, def, record, seen),
+            fl::ExprKind::Var(name) => {
+                if def.args.contains(name) || (self.in_ensures && *name == "result") {
+                    return None;
+                }
+                let i = *self.globals.get(name)?;
+                let global = &definitions[i];
+                if !global.args.is_empty() || seen.contains(&i) {
+                    return None;
+                }
+                seen.push(i);
+                self.known_fields(definitions, global, &global.body, seen)
+            }
+            _ => None,
+        }
+    }
+
+    // Reports a field the record is known not to have, otherwise it only has to be in some literal
+    fn report_missing_field(&self, known: Option<&[&'src str]>, field: &str, span: Span) -> bool {
+        let Some(known) = known else {
+            return false;
+        };
+        if known.contains(&field) {
+            return false;
+        }
+        let note = if known.is_empty() {
+            "the record has no fields".to_string()
+        } else {
+            format!("the record has the fields {}", known.join(", "))
+        };
+        self.emitter.emit(
+            Diagnostic::error(
+                Code::UnknownField,
+                Some(span),
+                format!("Record has no field {}", field),
+            )
+            .with_note(note),
+        );
+        true
+    }
+
+    fn check_duplicate_fields(&self, span: Span, fields: &[(&'src str, fl::Expr<'src>)]) {
+        for (i, (name, _)) in fields.iter().enumerate() {
+            if fields[..i].iter().any(|(other, _)| other == name) {
+                self.error(
+                    Code::DuplicateField,
+                    span,
+                    format!("Duplicate field: {}", name),
+                );
+            }
+        }
     }
 
     fn convert_body(
@@ -93,26 +279,43 @@ impl<'src> FtoH<'src> {
         definitions: &fl::Program<'src>,
         def: &fl::Definition<'src>,
         body: &fl::Expr<'src>,
-    ) -> Option<hir::Expr<'src>> {
-        Some(match body {
-            fl::Expr::Var(name) => {
-                if let Some(_) = def.args.iter().position(|arg| arg == name) {
+    ) -> hir::Expr<'src> {
+        let kind = match &body.kind {
+            fl::ExprKind::Var(name) => {
+                if self.in_ensures && *name == "result" && !def.args.contains(name) {
+                    hir::ExprKind::Local(obf_var!(def.name, name))
+                } else if def.args.contains(name) {
                     if let Some(idx) = self.globals.get(name) {
-                        self.error(format!(
-                            "Variable {} shadows global name {}",
-                            name, definitions[*idx].name
-                        ));
-                        return None;
+                        self.emitter.emit(
+                            Diagnostic::error(
+                                Code::ShadowedGlobal,
+                                Some(body.span),
+                                format!(
+                                    "Variable {} shadows global name {}",
+                                    name, definitions[*idx].name
+                                ),
+                            )
+                            .with_label(definitions[*idx].span, "global defined here"),
+                        );
+                        return Self::invalid(body.span);
                     }
-                    hir::Expr::Local(obf_var!(def.name, name))
-                } else if let Some(_) = self.globals.get(name) {
-                    hir::Expr::Global(name)
+                    hir::ExprKind::Local(obf_var!(def.name, name))
+                } else if self.globals.contains_key(name) {
+                    hir::ExprKind::Global(name)
+                } else if *name == INPUT {
+                    hir::ExprKind::Input
+                } else if *name == READ_VALUE {
+                    hir::ExprKind::ReadValue
                 } else {
-                    self.error(format!("Undefined variable: {}", name));
-                    return None;
+                    self.error(
+                        Code::UndefinedVariable,
+                        body.span,
+                        format!("Undefined variable: {}", name),
+                    );
+                    return Self::invalid(body.span);
                 }
             }
-            fl::Expr::Atom(atom_name) => {
+            fl::ExprKind::Atom(atom_name) => {
                 let atom = if let Some(atom) = self.atoms_map.get(atom_name) {
                     *atom
                 } else {
@@ -121,54 +324,90 @@ impl<'src> FtoH<'src> {
                     self.atom_names.push(atom_name);
                     atom
                 };
-                hir::Expr::Atom(atom)
+                hir::ExprKind::Atom(atom)
+            }
+            fl::ExprKind::Num(num) => hir::ExprKind::Num(*num),
+            fl::ExprKind::Extern(symbol, lazy) => {
+                if let Some(i) = self.globals.get(symbol) {
+                    self.emitter.emit(
+                        Diagnostic::error(
+                            Code::ExternClash,
+                            Some(body.span),
+                            format!(
+                                "C function {} of extern {} clashes with a definition of the same name",
+                                symbol, def.name
+                            ),
+                        )
+                        .with_label(definitions[*i].span, "definition here")
+                        .with_help("definitions are compiled to C functions of the same name"),
+                    );
+                    return Self::invalid(body.span);
+                }
+                if *symbol == "main"
+                    || RESERVED_PREFIXES
+                        .iter()
+                        .any(|prefix| symbol.starts_with(prefix))
+                {
+                    self.emitter.emit(
+                        Diagnostic::error(
+                            Code::ExternClash,
+                            Some(body.span),
+                            format!(
+                                "C function {} of extern {} clashes with the names of the generated code",
+                                symbol, def.name
+                            ),
+                        )
+                        .with_help("main, and names starting with fl_, __ or IC_, are reserved"),
+                    );
+                    return Self::invalid(body.span);
+                }
+                hir::ExprKind::Extern(symbol, lazy.clone())
             }
-            fl::Expr::Num(num) => hir::Expr::Num(*num),
-            fl::Expr::Add(lhs, rhs) => hir::Expr::Add(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Add(lhs, rhs) => hir::ExprKind::Add(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Sub(lhs, rhs) => hir::Expr::Sub(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Sub(lhs, rhs) => hir::ExprKind::Sub(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Mul(lhs, rhs) => hir::Expr::Mul(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Mul(lhs, rhs) => hir::ExprKind::Mul(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Eq(lhs, rhs) => hir::Expr::Eq(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Eq(lhs, rhs) => hir::ExprKind::Eq(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Neq(lhs, rhs) => hir::Expr::Neq(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Neq(lhs, rhs) => hir::ExprKind::Neq(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Lt(lhs, rhs) => hir::Expr::Lt(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Lt(lhs, rhs) => hir::ExprKind::Lt(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Le(lhs, rhs) => hir::Expr::Le(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Le(lhs, rhs) => hir::ExprKind::Le(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Gt(lhs, rhs) => hir::Expr::Gt(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Gt(lhs, rhs) => hir::ExprKind::Gt(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::Ge(lhs, rhs) => hir::Expr::Ge(
-                Box::new(self.convert_body(definitions, def, lhs)?),
-                Box::new(self.convert_body(definitions, def, rhs)?),
+            fl::ExprKind::Ge(lhs, rhs) => hir::ExprKind::Ge(
+                Box::new(self.convert_body(definitions, def, lhs)),
+                Box::new(self.convert_body(definitions, def, rhs)),
             ),
-            fl::Expr::IsPair(expr) => {
-                hir::Expr::IsPair(Box::new(self.convert_body(definitions, def, expr)?))
+            fl::ExprKind::IsPair(expr) => {
+                hir::ExprKind::IsPair(Box::new(self.convert_body(definitions, def, expr)))
             }
-            fl::Expr::If(cond, then, els) => hir::Expr::If(
-                Box::new(self.convert_body(definitions, def, cond)?),
-                Box::new(self.convert_body(definitions, def, then)?),
-                Box::new(self.convert_body(definitions, def, els)?),
+            fl::ExprKind::If(cond, then, els) => hir::ExprKind::If(
+                Box::new(self.convert_body(definitions, def, cond)),
+                Box::new(self.convert_body(definitions, def, then)),
+                Box::new(self.convert_body(definitions, def, els)),
             ),
-            fl::Expr::Call(name, args) => {
+            fl::ExprKind::Call(name, args) => {
                 if let Some(i) = self.globals.get(name) {
                     let callee = &definitions[*i];
                     if callee.args.len() == args.len() {
@@ -176,46 +415,238 @@ impl<'src> FtoH<'src> {
                         let curr = *calls;
                         *calls += 1;
 
-                        hir::Expr::Call(
+                        hir::ExprKind::Call(
                             name,
                             args.iter()
                                 .map(|arg| self.convert_body(definitions, def, arg))
-                                .collect::<Option<Box<[_]>>>()?,
+                                .collect::<Box<[_]>>(),
                             curr,
                         )
                     } else {
-                        self.error(format!(
-                            "Function {} is called with {} arguments, but is of arity {}.",
-                            name,
-                            args.len(),
-                            callee.args.len()
-                        ));
-                        return None;
+                        self.emitter.emit(
+                            Diagnostic::error(
+                                Code::ArityMismatch,
+                                Some(body.span),
+                                format!(
+                                    "Function {} is called with {} arguments, but is of arity {}",
+                                    name,
+                                    args.len(),
+                                    callee.args.len()
+                                ),
+                            )
+                            .with_label(callee.span, format!("{} is defined here", name)),
+                        );
+                        return self.invalid_call(definitions, def, body.span, args);
                     }
+                } else if let Some(expr) =
+                    self.convert_builtin(definitions, def, body.span, name, args)
+                {
+                    return expr;
                 } else {
-                    self.error(format!("Undefined function: {}", name));
-                    return None;
+                    self.error(
+                        Code::UndefinedFunction,
+                        body.span,
+                        format!("Undefined function: {}", name),
+                    );
+                    return self.invalid_call(definitions, def, body.span, args);
                 }
             }
-            fl::Expr::Cons(lhs, rhs) => {
+            fl::ExprKind::Cons(lhs, rhs) => {
                 let cc = self.cons_calls;
                 self.cons_calls += 1;
-                hir::Expr::Cons(
-                    Box::new(self.convert_body(definitions, def, lhs)?),
-                    Box::new(self.convert_body(definitions, def, rhs)?),
+                hir::ExprKind::Cons(
+                    Box::new(self.convert_body(definitions, def, lhs)),
+                    Box::new(self.convert_body(definitions, def, rhs)),
                     cc,
                 )
             }
-            fl::Expr::Car(expr) => {
-                hir::Expr::Car(Box::new(self.convert_body(definitions, def, expr)?))
+            fl::ExprKind::Car(expr) => {
+                hir::ExprKind::Car(Box::new(self.convert_body(definitions, def, expr)))
+            }
+            fl::ExprKind::Cdr(expr) => {
+                hir::ExprKind::Cdr(Box::new(self.convert_body(definitions, def, expr)))
+            }
+            fl::ExprKind::Record(fields) => {
+                self.check_duplicate_fields(body.span, fields);
+                if fields.len() > MAX_FIELDS {
+                    self.emitter.emit(
+                        Diagnostic::error(
+                            Code::TooManyFields,
+                            Some(body.span),
+                            format!("Record has {} fields", fields.len()),
+                        )
+                        .with_help(format!("a record has at most {MAX_FIELDS} fields")),
+                    );
+                }
+                let mut fields = fields.iter().collect::<Vec<_>>();
+                fields.sort_by_key(|(name, _)| *name);
+
+                let names = fields.iter().map(|(name, _)| *name).collect::<Box<[_]>>();
+                let shape = if let Some(shape) = self.shapes.iter().position(|s| *s == names) {
+                    shape
+                } else {
+                    self.shapes.push(names);
+                    self.shapes.len() - 1
+                };
+
+                let first = self.field_calls;
+                self.field_calls += fields.len();
+                hir::ExprKind::Record(
+                    shape,
+                    fields
+                        .iter()
+                        .map(|(_, value)| self.convert_body(definitions, def, value))
+                        .collect::<Box<[_]>>(),
+                    first,
+                )
+            }
+            fl::ExprKind::Field(record, name) => {
+                let known = self.known_fields(definitions, def, record, &mut Vec::new());
+                if !self.report_missing_field(known.as_deref(), name, body.span) {
+                    self.used_fields.push((name, body.span));
+                }
+                hir::ExprKind::Field(Box::new(self.convert_body(definitions, def, record)), name)
             }
-            fl::Expr::Cdr(expr) => {
-                hir::Expr::Cdr(Box::new(self.convert_body(definitions, def, expr)?))
+            fl::ExprKind::Update(record, fields) => {
+                self.check_duplicate_fields(body.span, fields);
+                let known = self.known_fields(definitions, def, record, &mut Vec::new());
+                let mut record = self.convert_body(definitions, def, record);
+                for (name, value) in fields.iter() {
+                    if !self.report_missing_field(known.as_deref(), name, body.span) {
+                        self.used_fields.push((name, body.span));
+                    }
+                    let fc = self.field_calls;
+                    self.field_calls += 1;
+                    record = hir::Expr::new(
+                        hir::ExprKind::Update(
+                            Box::new(record),
+                            name,
+                            Box::new(self.convert_body(definitions, def, value)),
+                            fc,
+                        ),
+                        body.span,
+                    );
+                }
+                return record;
             }
-        })
+        };
+        hir::Expr::new(kind, body.span)
+    }
+
+    // A call of a builtin, or None if name isn't one
+    fn convert_builtin(
+        &mut self,
+        definitions: &fl::Program<'src>,
+        def: &fl::Definition<'src>,
+        span: Span,
+        name: &'src str,
+        args: &[fl::Expr<'src>],
+    ) -> Option<hir::Expr<'src>> {
+        let arity = match name {
+            ARRAY | AT => 2,
+            LENGTH => 1,
+            _ => return None,
+        };
+        if args.len() != arity {
+            self.error(
+                Code::ArityMismatch,
+                span,
+                format!(
+                    "Builtin {} is called with {} arguments, but is of arity {}",
+                    name,
+                    args.len(),
+                    arity
+                ),
+            );
+            return Some(self.invalid_call(definitions, def, span, args));
+        }
+
+        let kind = match name {
+            ARRAY => {
+                let Some(function) = self.array_function(definitions, &args[1]) else {
+                    return Some(self.invalid_call(definitions, def, span, &args[..1]));
+                };
+                hir::ExprKind::Array(
+                    Box::new(self.convert_body(definitions, def, &args[0])),
+                    function,
+                )
+            }
+            AT => hir::ExprKind::Index(
+                Box::new(self.convert_body(definitions, def, &args[0])),
+                Box::new(self.convert_body(definitions, def, &args[1])),
+            ),
+            _ => hir::ExprKind::Length(Box::new(self.convert_body(definitions, def, &args[0]))),
+        };
+        Some(hir::Expr::new(kind, span))
+    }
+
+    // The function of arity 1 that gives the elements of an array, named by its second argument
+    fn array_function(
+        &mut self,
+        definitions: &fl::Program<'src>,
+        arg: &fl::Expr<'src>,
+    ) -> Option<&'src str> {
+        let fl::ExprKind::Var(function) = arg.kind else {
+            self.emitter.emit(
+                Diagnostic::error(
+                    Code::ArrayFunctionArity,
+                    Some(arg.span),
+                    "Array elements are given by the name of a function",
+                )
+                .with_help("define a function of arity 1 from the index to the element, as in 'array(n, f)'"),
+            );
+            return None;
+        };
+        let Some(i) = self.globals.get(function) else {
+            self.error(
+                Code::UndefinedFunction,
+                arg.span,
+                format!("Undefined function: {}", function),
+            );
+            return None;
+        };
+        let arity = definitions[*i].args.len();
+        if arity != 1 {
+            self.emitter.emit(
+                Diagnostic::error(
+                    Code::ArrayFunctionArity,
+                    Some(arg.span),
+                    format!(
+                        "Array elements are given by a function of arity 1, but {} is of arity {}",
+                        function, arity
+                    ),
+                )
+                .with_label(
+                    definitions[*i].span,
+                    format!("{} is defined here", function),
+                ),
+            );
+            return None;
+        }
+        Some(function)
+    }
+
+    // Stands in for an expression that failed to convert, so that the rest of the program is still checked
+    fn invalid(span: Span) -> hir::Expr<'src> {
+        hir::Expr::new(hir::ExprKind::Atom(0), span)
+    }
+
+    // The arguments of an invalid call are checked all the same
+    fn invalid_call(
+        &mut self,
+        definitions: &fl::Program<'src>,
+        def: &fl::Definition<'src>,
+        span: Span,
+        args: &[fl::Expr<'src>],
+    ) -> hir::Expr<'src> {
+        for arg in args.iter() {
+            self.convert_body(definitions, def, arg);
+        }
+        Self::invalid(span)
     }
 
-    fn error<S: AsRef<str>>(&self, message: S) {
-        eprintln!("[Error]: {}.", message.as_ref());
+    fn error<S: Into<String>>(&self, code: Code, span: Span, message: S) {
+        self.emitter
+            .emit(Diagnostic::error(code, Some(span), message));
     }
 }
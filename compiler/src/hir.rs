@@ -6,14 +6,20 @@
 // 2: Function local argument's names have been uniquified.
 // 3: Uses of function local variables and global variables
 //    have been separated.
+// 4: The fields of record literals have been sorted into
+//    shapes, and updates replace a single field.
 
 use std::collections::HashMap;
 
+use crate::loc::Span;
+
 #[derive(Debug)]
 pub struct Program<'src> {
     pub definitions: Box<[Definition<'src>]>,
     pub var_indices: HashMap<String, usize>,
     pub atoms: Box<[&'src str]>,
+    // The sorted field names of each record literal, indexed by shape
+    pub shapes: Box<[Box<[&'src str]>]>,
 }
 
 impl<'src> Program<'src> {
@@ -21,11 +27,13 @@ impl<'src> Program<'src> {
         definitions: Box<[Definition<'src>]>,
         var_indices: HashMap<String, usize>,
         atoms: Box<[&'src str]>,
+        shapes: Box<[Box<[&'src str]>]>,
     ) -> Self {
         Self {
             definitions,
             var_indices,
             atoms,
+            shapes,
         }
     }
 }
@@ -33,22 +41,62 @@ impl<'src> Program<'src> {
 #[derive(Debug)]
 pub struct Definition<'src> {
     pub name: &'src str,
+    // From the name, or 'extern', to the end of the body
+    pub span: Span,
     pub args: Box<[String]>,
+    pub arg_spans: Box<[Span]>,
     pub body: Expr<'src>,
+    pub requires: Option<Expr<'src>>,
+    // The returned value is the local following the arguments
+    pub ensures: Option<Expr<'src>>,
+    pub memo: bool,
 }
 
 impl<'src> Definition<'src> {
-    pub fn new(name: &'src str, args: Box<[String]>, body: Expr<'src>) -> Self {
-        Self { name, args, body }
+    pub fn new(
+        name: &'src str,
+        span: Span,
+        args: Box<[String]>,
+        arg_spans: Box<[Span]>,
+        body: Expr<'src>,
+        requires: Option<Expr<'src>>,
+        ensures: Option<Expr<'src>>,
+    ) -> Self {
+        Self {
+            name,
+            span,
+            args,
+            arg_spans,
+            body,
+            requires,
+            ensures,
+            memo: false,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Expr<'src> {
+    pub kind: ExprKind<'src>,
+    pub span: Span,
+}
+
+impl<'src> Expr<'src> {
+    pub fn new(kind: ExprKind<'src>, span: Span) -> Self {
+        Self { kind, span }
     }
 }
 
 #[derive(Debug)]
-pub enum Expr<'src> {
+pub enum ExprKind<'src> {
     Local(String),
     Global(&'src str),
     Atom(usize),
     Num(i64),
+    Input,
+    ReadValue,
+    // The C function to call, and which of the arguments are passed lazily
+    Extern(&'src str, Box<[bool]>),
     Add(Box<Expr<'src>>, Box<Expr<'src>>),
     Sub(Box<Expr<'src>>, Box<Expr<'src>>),
     Mul(Box<Expr<'src>>, Box<Expr<'src>>),
@@ -64,4 +112,13 @@ pub enum Expr<'src> {
     Cons(Box<Expr<'src>>, Box<Expr<'src>>, usize),
     Car(Box<Expr<'src>>),
     Cdr(Box<Expr<'src>>),
+    // The length, and the function of arity 1 that gives the element at an index
+    Array(Box<Expr<'src>>, &'src str),
+    Index(Box<Expr<'src>>, Box<Expr<'src>>),
+    Length(Box<Expr<'src>>),
+    // The shape, the fields in its order and the index of the first field
+    Record(usize, Box<[Expr<'src>]>, usize),
+    Field(Box<Expr<'src>>, &'src str),
+    // The record, the replaced field, its new value and its index
+    Update(Box<Expr<'src>>, &'src str, Box<Expr<'src>>, usize),
 }
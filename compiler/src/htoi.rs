@@ -19,36 +19,49 @@ impl<'src> HtoI<'src> {
     pub fn convert(mut self) -> il::Program<'src> {
         let mut actuals = self.make_indices();
         let mut conses = Vec::new();
+        let mut fields = Vec::new();
 
         let mut definitions = self
             .program
             .definitions
             .iter()
             .map(|def| {
-                il::Definition::new(
+                let mut definition = il::Definition::new(
                     def.name.to_string(),
+                    def.name,
                     def.args.clone(),
-                    self.convert_expr(&mut actuals, &mut conses, &def.body),
+                    self.convert_expr(def.name, &mut actuals, &mut conses, &mut fields, &def.body),
                     true,
-                )
+                );
+                definition.requires = def.requires.as_ref().map(|requires| {
+                    self.convert_expr(def.name, &mut actuals, &mut conses, &mut fields, requires)
+                });
+                definition.ensures = def.ensures.as_ref().map(|ensures| {
+                    self.convert_expr(def.name, &mut actuals, &mut conses, &mut fields, ensures)
+                });
+                definition.span = def.span;
+                definition.memo = def.memo;
+                definition
             })
             .collect();
 
         self.make_actuals(&mut actuals, &mut definitions);
         self.make_conses(&mut conses, &mut definitions);
+        self.make_fields(&mut fields, &mut definitions);
 
         il::Program::new(
             definitions.into_boxed_slice(),
             self.program.var_indices,
             self.program.atoms,
+            self.program.shapes,
         )
     }
 
-    fn make_indices(&mut self) -> Vec<Vec<Vec<il::Expr<'src>>>> {
+    fn make_indices(&mut self) -> Vec<Vec<Vec<(&'src str, il::Expr<'src>)>>> {
         let mut actuals = Vec::new();
         let mut idx = 0;
         for def in self.program.definitions.iter() {
-            if def.args.len() == 0 {
+            if def.args.is_empty() {
                 continue;
             }
             self.func_idx.insert(def.name, idx);
@@ -65,20 +78,21 @@ impl<'src> HtoI<'src> {
 
     fn make_actuals(
         &mut self,
-        actuals: &mut Vec<Vec<Vec<il::Expr<'src>>>>,
+        actuals: &mut Vec<Vec<Vec<(&'src str, il::Expr<'src>)>>>,
         definitions: &mut Vec<il::Definition<'src>>,
     ) {
         self.program
             .definitions
             .iter()
-            .filter(|def| def.args.len() > 0)
-            .zip(actuals.into_iter())
+            .filter(|def| !def.args.is_empty())
+            .zip(actuals.iter_mut())
             .for_each(|(def, actuals)| {
                 for (j, arg) in def.args.iter().enumerate() {
-                    let actuals = std::mem::replace(&mut actuals[j], Vec::new());
-                    for (i, actual) in actuals.into_iter().enumerate() {
+                    let actuals = std::mem::take(&mut actuals[j]);
+                    for (i, (origin, actual)) in actuals.into_iter().enumerate() {
                         definitions.push(il::Definition::new(
                             format!("{}_{}", arg, i),
+                            origin,
                             def.args.clone(),
                             actual,
                             false,
@@ -90,19 +104,21 @@ impl<'src> HtoI<'src> {
 
     fn make_conses(
         &mut self,
-        conses: &mut Vec<(il::Expr<'src>, il::Expr<'src>)>,
+        conses: &mut Vec<(&'src str, il::Expr<'src>, il::Expr<'src>)>,
         definitions: &mut Vec<il::Definition<'src>>,
     ) {
-        let conses = std::mem::replace(conses, Vec::new());
-        for (i, (car, cdr)) in conses.into_iter().enumerate() {
+        let conses = std::mem::take(conses);
+        for (i, (origin, car, cdr)) in conses.into_iter().enumerate() {
             definitions.push(il::Definition::new(
                 format!("__car_{}", i),
+                origin,
                 Vec::new().into_boxed_slice(),
                 car,
                 false,
             ));
             definitions.push(il::Definition::new(
                 format!("__cdr_{}", i),
+                origin,
                 Vec::new().into_boxed_slice(),
                 cdr,
                 false,
@@ -110,85 +126,141 @@ impl<'src> HtoI<'src> {
         }
     }
 
+    fn make_fields(
+        &mut self,
+        fields: &mut Vec<(usize, &'src str, il::Expr<'src>)>,
+        definitions: &mut Vec<il::Definition<'src>>,
+    ) {
+        let fields = std::mem::take(fields);
+        for (i, origin, field) in fields.into_iter() {
+            definitions.push(il::Definition::new(
+                format!("__field_{}", i),
+                origin,
+                Vec::new().into_boxed_slice(),
+                field,
+                false,
+            ));
+        }
+    }
+
     fn convert_expr(
         &self,
-        actuals: &mut Vec<Vec<Vec<il::Expr<'src>>>>,
-        conses: &mut Vec<(il::Expr<'src>, il::Expr<'src>)>,
+        origin: &'src str,
+        actuals: &mut Vec<Vec<Vec<(&'src str, il::Expr<'src>)>>>,
+        conses: &mut Vec<(&'src str, il::Expr<'src>, il::Expr<'src>)>,
+        fields: &mut Vec<(usize, &'src str, il::Expr<'src>)>,
         expr: &hir::Expr<'src>,
     ) -> il::Expr<'src> {
-        match expr {
-            hir::Expr::Local(name) => il::Expr::Var(name.clone()),
+        let kind = match &expr.kind {
+            hir::ExprKind::Local(name) => il::ExprKind::Var(name.clone()),
             // We always plug in 0, doesn't matter...
-            hir::Expr::Global(name) => il::Expr::Call(name, 0),
-            hir::Expr::Atom(index) => il::Expr::Atom(*index),
-            hir::Expr::Num(num) => il::Expr::Num(*num),
-            hir::Expr::Add(left, right) => il::Expr::Add(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Global(name) => il::ExprKind::Call(name, 0),
+            hir::ExprKind::Atom(index) => il::ExprKind::Atom(*index),
+            hir::ExprKind::Num(num) => il::ExprKind::Num(*num),
+            hir::ExprKind::Input => il::ExprKind::Input,
+            hir::ExprKind::ReadValue => il::ExprKind::ReadValue,
+            hir::ExprKind::Extern(symbol, lazy) => il::ExprKind::Extern(symbol, lazy.clone()),
+            hir::ExprKind::Add(left, right) => il::ExprKind::Add(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Sub(left, right) => il::Expr::Sub(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Sub(left, right) => il::ExprKind::Sub(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Mul(left, right) => il::Expr::Mul(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Mul(left, right) => il::ExprKind::Mul(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Eq(left, right) => il::Expr::Eq(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Eq(left, right) => il::ExprKind::Eq(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Neq(left, right) => il::Expr::Neq(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Neq(left, right) => il::ExprKind::Neq(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Lt(left, right) => il::Expr::Lt(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Lt(left, right) => il::ExprKind::Lt(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Gt(left, right) => il::Expr::Gt(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Gt(left, right) => il::ExprKind::Gt(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Le(left, right) => il::Expr::Le(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Le(left, right) => il::ExprKind::Le(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::Ge(left, right) => il::Expr::Ge(
-                Box::new(self.convert_expr(actuals, conses, left)),
-                Box::new(self.convert_expr(actuals, conses, right)),
+            hir::ExprKind::Ge(left, right) => il::ExprKind::Ge(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, left)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, right)),
             ),
-            hir::Expr::IsPair(expr) => {
-                il::Expr::IsPair(Box::new(self.convert_expr(actuals, conses, expr)))
-            }
-            hir::Expr::If(cond, then, els) => il::Expr::If(
-                Box::new(self.convert_expr(actuals, conses, cond)),
-                Box::new(self.convert_expr(actuals, conses, then)),
-                Box::new(self.convert_expr(actuals, conses, els)),
+            hir::ExprKind::IsPair(expr) => il::ExprKind::IsPair(Box::new(
+                self.convert_expr(origin, actuals, conses, fields, expr),
+            )),
+            hir::ExprKind::If(cond, then, els) => il::ExprKind::If(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, cond)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, then)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, els)),
             ),
-            hir::Expr::Call(name, args, i) => {
+            hir::ExprKind::Call(name, args, i) => {
                 for (idx, arg) in args.iter().enumerate() {
                     let curr_actuals_idx = actuals[self.func_idx[name]][idx].len();
-                    actuals[self.func_idx[name]][idx].push(il::Expr::Atom(0)); // TODO: temporary
-                    let expr = self.convert_expr(actuals, conses, arg);
-                    actuals[self.func_idx[name]][idx][curr_actuals_idx] = expr;
+                    actuals[self.func_idx[name]][idx]
+                        .push((origin, il::Expr::new(il::ExprKind::Atom(0), arg.span))); // TODO: temporary
+                    let expr = self.convert_expr(origin, actuals, conses, fields, arg);
+                    actuals[self.func_idx[name]][idx][curr_actuals_idx] = (origin, expr);
                 }
-                il::Expr::Call(name, *i)
+                il::ExprKind::Call(name, *i)
             }
-            hir::Expr::Cons(left, right, i) => {
+            hir::ExprKind::Cons(left, right, i) => {
                 let curr_conses_idx = conses.len();
-                conses.push((il::Expr::Atom(0), il::Expr::Atom(0))); // TODO: temporary
-                let left = self.convert_expr(actuals, conses, left);
-                let right = self.convert_expr(actuals, conses, right);
-                conses[curr_conses_idx] = (left, right);
-                il::Expr::Cons(*i)
+                conses.push((
+                    origin,
+                    il::Expr::new(il::ExprKind::Atom(0), left.span),
+                    il::Expr::new(il::ExprKind::Atom(0), right.span),
+                )); // TODO: temporary
+                let left = self.convert_expr(origin, actuals, conses, fields, left);
+                let right = self.convert_expr(origin, actuals, conses, fields, right);
+                conses[curr_conses_idx] = (origin, left, right);
+                il::ExprKind::Cons(*i)
             }
-            hir::Expr::Car(expr) => {
-                il::Expr::Car(Box::new(self.convert_expr(actuals, conses, expr)))
+            hir::ExprKind::Car(expr) => il::ExprKind::Car(Box::new(
+                self.convert_expr(origin, actuals, conses, fields, expr),
+            )),
+            hir::ExprKind::Cdr(expr) => il::ExprKind::Cdr(Box::new(
+                self.convert_expr(origin, actuals, conses, fields, expr),
+            )),
+            hir::ExprKind::Array(length, function) => il::ExprKind::Array(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, length)),
+                function,
+            ),
+            hir::ExprKind::Index(array, index) => il::ExprKind::Index(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, array)),
+                Box::new(self.convert_expr(origin, actuals, conses, fields, index)),
+            ),
+            hir::ExprKind::Length(expr) => il::ExprKind::Length(Box::new(
+                self.convert_expr(origin, actuals, conses, fields, expr),
+            )),
+            hir::ExprKind::Record(shape, values, first) => {
+                for (i, value) in values.iter().enumerate() {
+                    let value = self.convert_expr(origin, actuals, conses, fields, value);
+                    fields.push((first + i, origin, value));
+                }
+                il::ExprKind::Record(*shape, *first)
             }
-            hir::Expr::Cdr(expr) => {
-                il::Expr::Cdr(Box::new(self.convert_expr(actuals, conses, expr)))
+            hir::ExprKind::Field(record, name) => il::ExprKind::Field(
+                Box::new(self.convert_expr(origin, actuals, conses, fields, record)),
+                name,
+            ),
+            hir::ExprKind::Update(record, name, value, i) => {
+                let record = self.convert_expr(origin, actuals, conses, fields, record);
+                let value = self.convert_expr(origin, actuals, conses, fields, value);
+                fields.push((*i, origin, value));
+                il::ExprKind::Update(Box::new(record), name, *i)
             }
-        }
+        };
+        il::Expr::new(kind, expr.span)
     }
 }
@@ -1,10 +1,14 @@
 use std::collections::HashMap;
 
+use crate::loc::Span;
+
 #[derive(Debug)]
 pub struct Program<'src> {
     pub definitions: Box<[Definition<'src>]>,
     pub var_indices: HashMap<String, usize>,
     pub atoms: Box<[&'src str]>,
+    // The sorted field names of each record literal, indexed by shape
+    pub shapes: Box<[Box<[&'src str]>]>,
 }
 
 impl<'src> Program<'src> {
@@ -12,11 +16,13 @@ impl<'src> Program<'src> {
         definitions: Box<[Definition<'src>]>,
         var_indices: HashMap<String, usize>,
         atoms: Box<[&'src str]>,
+        shapes: Box<[Box<[&'src str]>]>,
     ) -> Self {
         Self {
             definitions,
             var_indices,
             atoms,
+            shapes,
         }
     }
 }
@@ -24,27 +30,63 @@ impl<'src> Program<'src> {
 #[derive(Debug)]
 pub struct Definition<'src> {
     pub name: String,
+    // The fl definition the body was written in, which thunks share with their caller
+    pub origin: &'src str,
+    // Where the definition, or the expression of a thunk, is written
+    pub span: Span,
     pub args: Box<[String]>, // We need this field for generating the lars
     pub body: Expr<'src>,
     pub is_function: bool,
+    pub requires: Option<Expr<'src>>,
+    // Evaluated in a LAR that has the returned value after the arguments
+    pub ensures: Option<Expr<'src>>,
+    // Results are cached on the fully evaluated arguments
+    pub memo: bool,
 }
 
 impl<'src> Definition<'src> {
-    pub fn new(name: String, args: Box<[String]>, body: Expr<'src>, is_function: bool) -> Self {
+    pub fn new(
+        name: String,
+        origin: &'src str,
+        args: Box<[String]>,
+        body: Expr<'src>,
+        is_function: bool,
+    ) -> Self {
         Self {
             name,
+            origin,
+            span: body.span,
             args,
             body,
             is_function,
+            requires: None,
+            ensures: None,
+            memo: false,
         }
     }
 }
 
 #[derive(Debug)]
-pub enum Expr<'src> {
+pub struct Expr<'src> {
+    pub kind: ExprKind<'src>,
+    pub span: Span,
+}
+
+impl<'src> Expr<'src> {
+    pub fn new(kind: ExprKind<'src>, span: Span) -> Self {
+        Self { kind, span }
+    }
+}
+
+#[derive(Debug)]
+pub enum ExprKind<'src> {
     Var(String),
     Atom(usize),
     Num(i64),
+    Input,
+    ReadValue,
+    // The C function to call, and which of the arguments are passed lazily
+    Extern(&'src str, Box<[bool]>),
     Add(Box<Expr<'src>>, Box<Expr<'src>>),
     Sub(Box<Expr<'src>>, Box<Expr<'src>>),
     Mul(Box<Expr<'src>>, Box<Expr<'src>>),
@@ -60,4 +102,13 @@ pub enum Expr<'src> {
     Cons(usize),
     Car(Box<Expr<'src>>),
     Cdr(Box<Expr<'src>>),
+    // The length, and the function of arity 1 that gives the element at an index
+    Array(Box<Expr<'src>>, &'src str),
+    Index(Box<Expr<'src>>, Box<Expr<'src>>),
+    Length(Box<Expr<'src>>),
+    // The shape, and the index of its first field
+    Record(usize, usize),
+    Field(Box<Expr<'src>>, &'src str),
+    // The record, the replaced field and its index
+    Update(Box<Expr<'src>>, &'src str, usize),
 }
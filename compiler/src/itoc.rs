@@ -1,14 +1,110 @@
-use std::io::Write;
-use std::{fs::File, io::BufWriter};
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::io;
 
+use crate::backend::Backend;
 use crate::il;
+use crate::loc::Span;
+use crate::runtime::{fnv1a, FNV_OFFSET};
+use crate::Source;
 
-pub struct ItoC<'src> {
-    pub program: il::Program<'src>,
-    ic_home: String,
-    out: BufWriter<File>,
+// What the generated C source does
+#[derive(Clone, Debug)]
+pub struct Options {
+    // Stream the result to stdout as a list of characters
+    pub interact: bool,
+    // Build a static library with a C entry point per definition, instead of an executable
+    pub emit_lib: bool,
+    // Check the requires/ensures clauses of definitions at runtime
+    pub contracts: bool,
+}
+
+// Prefix of the C entry points of a library built with `emit_lib`
+pub const LIB_PREFIX: &str = "fl_";
+
+// The library's own entry points, which can't clash with those of definitions as fl names have no
+// underscores
+const LIB_INIT: &str = "fl_lib_init";
+const LIB_CLEANUP: &str = "fl_lib_cleanup";
+
+// Prefix of the C names of fl definitions in a split program, where they have external linkage
+const SYMBOL_PREFIX: &str = "__fl_";
+
+// Programs with more definitions than this, thunks included, are split into units of about as many
+const UNIT_SIZE: usize = 256;
+
+// The bucket of a key among `buckets`, by linear hashing: adding a bucket splits a single one of
+// the others, so only the keys of that one move
+fn linear_hash(key: u64, buckets: usize) -> usize {
+    let level = buckets.next_power_of_two() as u64;
+    let bucket = key % level;
+    if bucket < buckets as u64 {
+        bucket as usize
+    } else {
+        (key % (level / 2)) as usize
+    }
+}
+
+// Generates C for the runtime in runtime/, which the build step compiles and links against
+pub struct CBackend<'a> {
+    options: Options,
+    // The fl source, for the locations of runtime errors
+    source: Source<'a>,
+}
+
+impl<'a> CBackend<'a> {
+    pub fn new(options: Options, source: Source<'a>) -> Self {
+        Self { options, source }
+    }
+}
+
+impl Backend for CBackend<'_> {
+    fn extension(&self) -> &'static str {
+        "c"
+    }
+
+    fn header_extension(&self) -> &'static str {
+        "h"
+    }
+
+    fn generate(&self, program: il::Program, out: &mut dyn io::Write) -> io::Result<()> {
+        let code = ItoC::new(program, self.options.clone(), self.source).generate();
+        out.write_all(code.as_bytes())
+    }
+
+    // The declarations of a library's entry points, for C programs that call them
+    fn header(&self, program: &il::Program, out: &mut dyn io::Write) -> io::Result<()> {
+        out.write_all(ItoC::header(program).as_bytes())
+    }
+
+    // The program as translation units, so that a change only recompiles the units it touches:
+    // one for main or the entry points, and the others with the definitions, grouped by the fl
+    // definition they come from. Each unit declares only what it uses, so its C stays the same
+    // while its own definitions do. Small programs are a single unit, the same as `generate`'s.
+    fn generate_units(&self, program: il::Program) -> Vec<String> {
+        let groups = program.definitions.len().div_ceil(UNIT_SIZE);
+        let itoc = ItoC::new(program, self.options.clone(), self.source);
+        if groups <= 1 {
+            vec![itoc.generate()]
+        } else {
+            itoc.generate_split(groups)
+        }
+    }
+}
+
+struct ItoC<'src> {
+    program: il::Program<'src>,
+    options: Options,
+    // The fl source, for the locations of runtime errors
+    source: Source<'src>,
+    out: String,
     tmp_cnt: usize,
+    loc_cnt: usize,
+    // The fl definition of the definition being generated
+    origin: &'src str,
     indentation: usize,
+    // The number of units the definitions are split into, or 0 for a single file
+    groups: usize,
 }
 
 macro_rules! w {
@@ -60,44 +156,93 @@ macro_rules! dedent {
 }
 
 impl<'src> ItoC<'src> {
-    pub fn new(program: il::Program<'src>, ic_home: String) -> Self {
-        std::fs::create_dir("_build").unwrap();
+    fn new(program: il::Program<'src>, options: Options, source: Source<'src>) -> Self {
         Self {
             program,
-            ic_home,
-            out: BufWriter::new(File::create("_build/out.c").unwrap()),
+            options,
+            source,
+            out: String::new(),
             tmp_cnt: 0,
+            loc_cnt: 0,
+            origin: "",
             indentation: 0,
+            groups: 0,
         }
     }
 
-    pub fn generate(mut self) {
+    fn generate(mut self) -> String {
         self.prelude();
         self.atom_names();
-        self.prototypes();
-        self.main();
-        self.definitions();
+        self.shapes(None);
+        self.prototypes(None, None);
+        if self.options.emit_lib {
+            self.entry_points();
+        } else {
+            self.main();
+        }
+        self.definitions(None);
 
-        self.compile();
+        self.out
     }
 
-    fn compile(&mut self) {
-        std::process::Command::new("gcc")
-            .arg("-o")
-            .arg("_build/out")
-            .arg("_build/out.c")
-            .arg("-O3")
-            .arg("-I")
-            .arg(self.ic_home.clone() + "/runtime/include")
-            .arg("-L")
-            .arg(self.ic_home.clone() + "/runtime/lib")
-            .arg("-l")
-            .arg("ic")
-            .spawn()
-            .unwrap();
+    // The first unit has main or the entry points, and the atoms' names they initialise the
+    // runtime with
+    fn generate_split(mut self, groups: usize) -> Vec<String> {
+        self.groups = groups;
+        if self.options.emit_lib {
+            self.entry_points();
+        } else {
+            self.main();
+        }
+        let mut bodies = vec![std::mem::take(&mut self.out)];
+        for group in 0..groups {
+            self.definitions(Some(group));
+            bodies.push(std::mem::take(&mut self.out));
+        }
+
+        let mut units = Vec::new();
+        for (i, body) in bodies.iter().enumerate() {
+            self.prelude();
+            if i == 0 {
+                self.atom_names();
+            }
+            let used = body
+                .split(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                .collect::<HashSet<_>>();
+            self.shapes(Some(&used));
+            self.prototypes(Some(&used), i.checked_sub(1));
+            self.out.push_str(body);
+            units.push(std::mem::take(&mut self.out));
+        }
+        units
+    }
+
+    // The C name of a definition. Split units link against each other, so fl names, which could
+    // clash with C's, are prefixed. Generated names already start with "__".
+    fn symbol(&self, name: &str) -> String {
+        if self.groups > 0 && !name.starts_with("__") {
+            format!("{}{}", SYMBOL_PREFIX, name)
+        } else {
+            name.to_string()
+        }
+    }
+
+    fn linkage(&self) -> &'static str {
+        if self.groups > 0 {
+            ""
+        } else {
+            "static "
+        }
+    }
+
+    // The group of a definition, the same for all those of an fl definition. When a growing program
+    // gets another group, only the units of the group it splits and of the new one change.
+    fn group(&self, def: &il::Definition) -> usize {
+        linear_hash(fnv1a(FNV_OFFSET, def.origin.as_bytes()), self.groups)
     }
 
     fn prelude(&mut self) {
+        wl!(self, "#include \"memo.h\"");
         wl!(self, "#include \"value.h\"");
         wl!(self, "#include <locale.h>");
         wl!(self, "#include <stdio.h>");
@@ -106,7 +251,7 @@ impl<'src> ItoC<'src> {
     }
 
     fn atom_names(&mut self) {
-        wl!(self, "const char* IC_atom_names[] = {{");
+        wl!(self, "static const char* __atom_names[] = {{");
 
         indent!(self);
         for atom in self.program.atoms.iter() {
@@ -118,44 +263,252 @@ impl<'src> ItoC<'src> {
         wl!(self, "");
     }
 
-    fn prototypes(&mut self) {
+    // The shapes of the record literals, and for each field that several shapes have, a table
+    // mapping shapes to its slot in them. Only those that are `used` are declared, if given.
+    fn shapes(&mut self, used: Option<&HashSet<&str>>) {
+        if self.program.shapes.is_empty() {
+            return;
+        }
+
+        let shapes = std::mem::take(&mut self.program.shapes);
+        if used.is_none_or(|used| used.contains("__shapes")) {
+            wl!(self, "static const IC_SHAPE __shapes[] = {{");
+            indent!(self);
+            for (id, shape) in shapes.iter().enumerate() {
+                let names = if shape.is_empty() {
+                    "NULL".to_string()
+                } else {
+                    let names = shape
+                        .iter()
+                        .map(|name| format!("\"{}\"", name))
+                        .collect::<Vec<_>>();
+                    format!("(const char*[]){{{}}}", names.join(", "))
+                };
+                wl!(self, "{{{}, {}, {}}},", id, shape.len(), names);
+            }
+            dedent!(self);
+            wl!(self, "}};");
+            wl!(self, "");
+        }
+
+        let mut fields = shapes
+            .iter()
+            .flat_map(|shape| shape.iter())
+            .filter(|field| {
+                shapes.iter().filter(|shape| shape.contains(field)).count() > 1
+                    && used.is_none_or(|used| used.contains(format!("__slots_{}", field).as_str()))
+            })
+            .collect::<Vec<_>>();
+        fields.sort();
+        fields.dedup();
+        for &field in fields.iter() {
+            let slots = shapes
+                .iter()
+                .map(|shape| match shape.iter().position(|name| name == field) {
+                    Some(slot) => slot.to_string(),
+                    None => "-1".to_string(),
+                })
+                .collect::<Vec<_>>();
+            wl!(
+                self,
+                "static const int __slots_{}[] = {{{}}};",
+                field,
+                slots.join(", ")
+            );
+        }
+        if !fields.is_empty() {
+            wl!(self, "");
+        }
+        self.program.shapes = shapes;
+    }
+
+    // Declares the definitions and C functions that are `used`, or all of them, and the memo
+    // tables of the definitions, only those of a `group` in a split program
+    fn prototypes(&mut self, used: Option<&HashSet<&str>>, group: Option<usize>) {
+        let is_used = |symbol: &str| used.is_none_or(|used| used.contains(symbol));
         for def in self.program.definitions.iter() {
-            wl!(self, "static IC_VALUE {}(IC_LAR_PROTO* lar);", def.name);
+            let symbol = self.symbol(&def.name);
+            if is_used(&symbol) {
+                wl!(
+                    self,
+                    "{}IC_VALUE {}(IC_LAR_PROTO* lar);",
+                    self.linkage(),
+                    symbol
+                );
+            }
         }
         wl!(self, "");
+
+        let mut externs = Vec::new();
+        for def in self.program.definitions.iter() {
+            if let il::ExprKind::Extern(symbol, lazy) = &def.body.kind {
+                if !externs.contains(symbol) && is_used(symbol) {
+                    externs.push(*symbol);
+                    let params = lazy
+                        .iter()
+                        .map(|lazy| if *lazy { "IC_THUNK" } else { "IC_VALUE" })
+                        .collect::<Vec<_>>();
+                    let params = if params.is_empty() {
+                        "void".to_string()
+                    } else {
+                        params.join(", ")
+                    };
+                    wl!(self, "IC_VALUE {}({});", symbol, params);
+                }
+            }
+        }
+        if !externs.is_empty() {
+            wl!(self, "");
+        }
+
+        let mut memos = false;
+        for def in self.program.definitions.iter().filter(|def| def.memo) {
+            if self.groups > 0 && group != Some(self.group(def)) {
+                continue;
+            }
+            wl!(
+                self,
+                "static IC_MEMO __{}_memo = IC_MEMO_INIT({});",
+                def.name,
+                def.args.len()
+            );
+            memos = true;
+        }
+        if memos {
+            wl!(self, "");
+        }
     }
 
-    fn definitions(&mut self) {
+    // The definitions of a group, or all of them
+    fn definitions(&mut self, group: Option<usize>) {
         let defs = std::mem::take(&mut self.program.definitions);
         for def in defs.iter() {
-            wl!(self, "static IC_VALUE {}(IC_LAR_PROTO* lar)", def.name);
+            if group.is_some_and(|group| self.group(def) != group) {
+                continue;
+            }
+            // Names of temporaries and locations only have to be unique within a function, and
+            // numbering them afresh keeps a unit's C from depending on the other units
+            if self.groups > 0 {
+                self.tmp_cnt = 0;
+                self.loc_cnt = 0;
+            }
+            self.origin = def.origin;
+            wl!(
+                self,
+                "{}IC_VALUE {}(IC_LAR_PROTO* lar)",
+                self.linkage(),
+                self.symbol(&def.name)
+            );
             wl!(self, "{{");
             indent!(self);
+            let (path, span) = self.source.locate(def.span);
+            wl!(
+                self,
+                "static const IC_DEMAND __demand = {{\"{}\", {{\"{}\", {}, {}, \"{}\"}}}};",
+                Self::demangle(def),
+                c_escape(path),
+                span.start.line,
+                span.start.col,
+                def.origin
+            );
+            wl!(self, "IC_DEMAND_PUSH(&__demand);");
             if def.is_function {
                 wl!(self, "IC_FUNCTION_PUSH(lar);");
             }
 
-            let res = self.convert_expr(&defs, &def.name, &def.body);
+            if def.memo {
+                // Calls are keyed on the fully evaluated arguments, so a hit skips the
+                // contracts as well as the body
+                wl!(self, "IC_VALUE __key[{}];", def.args.len().max(1));
+                for i in 0..def.args.len() {
+                    wl!(
+                        self,
+                        "__key[{}] = IC_value_force(IC_lar_get_arg(lar, {}));",
+                        i,
+                        i
+                    );
+                }
+                wl!(self, "IC_VALUE __memo;");
+                wl!(
+                    self,
+                    "if (!IC_memo_lookup(&__{}_memo, __key, &__memo)) {{",
+                    def.name
+                );
+                indent!(self);
+            }
+
+            if let Some(requires) = def.requires.as_ref().filter(|_| self.options.contracts) {
+                let cond = self.convert_expr(&defs, requires);
+                self.contract_check(def, cond, "precondition", &[]);
+            }
+
+            let res = self.convert_expr(&defs, &def.body);
+
+            if let Some(ensures) = def.ensures.as_ref().filter(|_| self.options.contracts) {
+                // The postcondition is evaluated in a copy of the LAR that has the returned
+                // value after the arguments, which the inner 'lar' shadows
+                wl!(
+                    self,
+                    "IC_LAR_PROTO* __post = IC_lar_extend(lar, {});",
+                    fmt_tmp!(res)
+                );
+                wl!(self, "{{");
+                indent!(self);
+                wl!(self, "IC_LAR_PROTO* lar = __post;");
+                wl!(self, "IC_FUNCTION_PUSH(lar);");
+                let cond = self.convert_expr(&defs, ensures);
+                self.contract_check(def, cond, "postcondition", &["result"]);
+                wl!(self, "IC_FUNCTION_POP(lar);");
+                dedent!(self);
+                wl!(self, "}}");
+            }
+
+            if def.memo {
+                wl!(self, "__memo = {};", fmt_tmp!(res));
+                wl!(self, "IC_memo_insert(&__{}_memo, __key, __memo);", def.name);
+                dedent!(self);
+                wl!(self, "}}");
+            }
 
             if def.is_function {
                 wl!(self, "IC_FUNCTION_POP(lar);");
             }
-            wl!(self, "return {};", fmt_tmp!(res));
+            wl!(self, "IC_DEMAND_POP();");
+            if def.memo {
+                wl!(self, "return __memo;");
+            } else {
+                wl!(self, "return {};", fmt_tmp!(res));
+            }
 
             dedent!(self);
             wl!(self, "}}");
             wl!(self, "");
         }
+        self.program.definitions = defs;
     }
 
-    fn convert_expr(
-        &mut self,
-        defs: &Box<[il::Definition]>,
-        f: &str,
-        expr: &il::Expr<'src>,
-    ) -> usize {
-        match expr {
-            il::Expr::Var(name) => {
+    fn contract_check(&mut self, def: &il::Definition, cond: usize, clause: &str, extra: &[&str]) {
+        let names = Self::fl_args(def)
+            .into_iter()
+            .chain(extra.iter().map(|name| name.to_string()))
+            .map(|name| format!("\"{}\"", name))
+            .collect::<Vec<_>>();
+        wl!(self, "if (!IC_IS_TRUTHY({})) {{", fmt_tmp!(cond));
+        indent!(self);
+        wl!(
+            self,
+            "IC_contract_error(\"{}\", \"{}\", lar, (const char*[]){{{}}});",
+            clause,
+            def.name,
+            names.join(", ")
+        );
+        dedent!(self);
+        wl!(self, "}}");
+    }
+
+    fn convert_expr(&mut self, defs: &[il::Definition], expr: &il::Expr<'src>) -> usize {
+        match &expr.kind {
+            il::ExprKind::Var(name) => {
                 let tmp = gen_tmp!(self);
 
                 wl!(
@@ -166,136 +519,109 @@ impl<'src> ItoC<'src> {
                 );
                 tmp
             }
-            il::Expr::Atom(atom) => {
+            il::ExprKind::Atom(atom) => {
                 let tmp = gen_tmp!(self);
                 wl!(self, "IC_VALUE {} = IC_ATOM({});", fmt_tmp!(tmp), atom);
                 tmp
             }
-            il::Expr::Num(num) => {
+            il::ExprKind::Num(num) => {
                 let tmp = gen_tmp!(self);
                 wl!(self, "IC_VALUE {} = IC_INTEGER({});", fmt_tmp!(tmp), num);
                 tmp
             }
-            il::Expr::Add(lhs, rhs) => {
+            il::ExprKind::Input => {
                 let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
-                wl!(
-                    self,
-                    "IC_VALUE {} = IC_add({}, {});",
-                    fmt_tmp!(tmp),
-                    fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
-                );
-                tmp
-            }
-            il::Expr::Sub(lhs, rhs) => {
-                let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
-                wl!(
-                    self,
-                    "IC_VALUE {} = IC_sub({}, {});",
-                    fmt_tmp!(tmp),
-                    fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
-                );
-                tmp
-            }
-            il::Expr::Mul(lhs, rhs) => {
-                let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
-                wl!(
-                    self,
-                    "IC_VALUE {} = IC_mul({}, {});",
-                    fmt_tmp!(tmp),
-                    fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
-                );
-                tmp
-            }
-            il::Expr::Eq(lhs, rhs) => {
-                let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
-                wl!(
-                    self,
-                    "IC_VALUE {} = IC_eq({}, {});",
-                    fmt_tmp!(tmp),
-                    fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
-                );
+                wl!(self, "IC_VALUE {} = IC_input();", fmt_tmp!(tmp));
                 tmp
             }
-            il::Expr::Neq(lhs, rhs) => {
+            il::ExprKind::ReadValue => {
                 let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
-                wl!(
-                    self,
-                    "IC_VALUE {} = IC_neq({}, {});",
-                    fmt_tmp!(tmp),
-                    fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
-                );
+                wl!(self, "IC_VALUE {} = IC_value_read();", fmt_tmp!(tmp));
                 tmp
             }
-            il::Expr::Lt(lhs, rhs) => {
+            il::ExprKind::Extern(symbol, lazy) => {
+                let args = lazy
+                    .iter()
+                    .enumerate()
+                    .map(|(i, lazy)| {
+                        if *lazy {
+                            format!("(IC_THUNK){{lar, {}}}", i)
+                        } else {
+                            let tmp = gen_tmp!(self);
+                            wl!(
+                                self,
+                                "IC_VALUE {} = IC_lar_get_arg(lar, {});",
+                                fmt_tmp!(tmp),
+                                i
+                            );
+                            fmt_tmp!(tmp)
+                        }
+                    })
+                    .collect::<Vec<_>>();
                 let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
                 wl!(
                     self,
-                    "IC_VALUE {} = IC_lt({}, {});",
+                    "IC_VALUE {} = {}({});",
                     fmt_tmp!(tmp),
-                    fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
+                    symbol,
+                    args.join(", ")
                 );
                 tmp
             }
-            il::Expr::Le(lhs, rhs) => {
+            il::ExprKind::Add(lhs, rhs) => {
                 let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
+                let lhs = self.convert_expr(defs, lhs);
+                let rhs = self.convert_expr(defs, rhs);
+                let loc = self.location(expr.span);
                 wl!(
                     self,
-                    "IC_VALUE {} = IC_le({}, {});",
+                    "IC_VALUE {} = IC_add_at({}, {}, {});",
                     fmt_tmp!(tmp),
                     fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
+                    fmt_tmp!(rhs),
+                    loc
                 );
                 tmp
             }
-            il::Expr::Gt(lhs, rhs) => {
+            il::ExprKind::Sub(lhs, rhs) => {
                 let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
+                let lhs = self.convert_expr(defs, lhs);
+                let rhs = self.convert_expr(defs, rhs);
+                let loc = self.location(expr.span);
                 wl!(
                     self,
-                    "IC_VALUE {} = IC_gt({}, {});",
+                    "IC_VALUE {} = IC_sub_at({}, {}, {});",
                     fmt_tmp!(tmp),
                     fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
+                    fmt_tmp!(rhs),
+                    loc
                 );
                 tmp
             }
-            il::Expr::Ge(lhs, rhs) => {
+            il::ExprKind::Mul(lhs, rhs) => {
                 let tmp = gen_tmp!(self);
-                let lhs = self.convert_expr(defs, f, lhs);
-                let rhs = self.convert_expr(defs, f, rhs);
+                let lhs = self.convert_expr(defs, lhs);
+                let rhs = self.convert_expr(defs, rhs);
+                let loc = self.location(expr.span);
                 wl!(
                     self,
-                    "IC_VALUE {} = IC_ge({}, {});",
+                    "IC_VALUE {} = IC_mul_at({}, {}, {});",
                     fmt_tmp!(tmp),
                     fmt_tmp!(lhs),
-                    fmt_tmp!(rhs)
+                    fmt_tmp!(rhs),
+                    loc
                 );
                 tmp
             }
-            il::Expr::IsPair(expr) => {
+            il::ExprKind::Eq(lhs, rhs) => self.convert_comparison(defs, "IC_eq", lhs, rhs),
+            il::ExprKind::Neq(lhs, rhs) => self.convert_comparison(defs, "IC_neq", lhs, rhs),
+            il::ExprKind::Lt(lhs, rhs) => self.convert_comparison(defs, "IC_lt", lhs, rhs),
+            il::ExprKind::Le(lhs, rhs) => self.convert_comparison(defs, "IC_le", lhs, rhs),
+            il::ExprKind::Gt(lhs, rhs) => self.convert_comparison(defs, "IC_gt", lhs, rhs),
+            il::ExprKind::Ge(lhs, rhs) => self.convert_comparison(defs, "IC_ge", lhs, rhs),
+            il::ExprKind::IsPair(expr) => {
                 let tmp = gen_tmp!(self);
-                let expr_res = self.convert_expr(defs, f, expr);
+                let expr_res = self.convert_expr(defs, expr);
                 wl!(
                     self,
                     "IC_VALUE {} = IC_IS_PAIR({});",
@@ -304,36 +630,41 @@ impl<'src> ItoC<'src> {
                 );
                 tmp
             }
-            il::Expr::If(cond, then, els) => {
+            il::ExprKind::If(cond, then, els) => {
                 let tmp = gen_tmp!(self);
                 wl!(self, "IC_VALUE {};", fmt_tmp!(tmp));
 
-                let cond_res = self.convert_expr(defs, f, cond);
+                let cond_res = self.convert_expr(defs, cond);
+                let loc = self.location(cond.span);
                 wl!(self, "if ({}.tag != IC_VALUE_ATOM) {{", fmt_tmp!(cond_res));
                 indent!(self);
-                wl!(self, "IC_runtime_error(\"if condition is not an atom\");");
+                wl!(
+                    self,
+                    "IC_runtime_error_at({}, \"if condition is not an atom\");",
+                    loc
+                );
                 dedent!(self);
                 wl!(self, "}} else if ({}.as.atom == 1) {{", fmt_tmp!(cond_res));
                 indent!(self);
-                let then_res = self.convert_expr(defs, f, then);
+                let then_res = self.convert_expr(defs, then);
                 wl!(self, "{} = {};", fmt_tmp!(tmp), fmt_tmp!(then_res));
                 dedent!(self);
                 wl!(self, "}} else {{");
                 indent!(self);
-                let els_res = self.convert_expr(defs, f, els);
+                let els_res = self.convert_expr(defs, els);
                 wl!(self, "{} = {};", fmt_tmp!(tmp), fmt_tmp!(els_res));
                 dedent!(self);
                 wl!(self, "}}");
                 tmp
             }
-            il::Expr::Call(callee, i) => {
+            il::ExprKind::Call(callee, i) => {
                 let tmp = gen_tmp!(self);
                 let def = defs.iter().find(|d| d.name == *callee).unwrap();
                 w!(
                     self,
                     "IC_VALUE {} = {}(IC_lar_new(lar, {}, (IC_LARF[]){{",
                     fmt_tmp!(tmp),
-                    callee,
+                    self.symbol(callee),
                     def.args.len()
                 );
                 for (idx, arg) in def.args.iter().enumerate() {
@@ -345,7 +676,7 @@ impl<'src> ItoC<'src> {
                 writeln!(self.out, "}}));").unwrap();
                 tmp
             }
-            il::Expr::Cons(i) => {
+            il::ExprKind::Cons(i) => {
                 let tmp = gen_tmp!(self);
                 wl!(
                     self,
@@ -356,39 +687,373 @@ impl<'src> ItoC<'src> {
                 );
                 tmp
             }
-            il::Expr::Car(expr) => {
-                let res = self.convert_expr(defs, f, expr);
+            il::ExprKind::Car(expr) => {
+                let res = self.convert_expr(defs, expr);
                 let tmp = gen_tmp!(self);
+                let loc = self.location(expr.span);
                 wl!(
                     self,
-                    "IC_VALUE {} = IC_car({});",
+                    "IC_VALUE {} = IC_car_at({}, {});",
                     fmt_tmp!(tmp),
-                    fmt_tmp!(res)
+                    fmt_tmp!(res),
+                    loc
                 );
                 tmp
             }
-            il::Expr::Cdr(expr) => {
-                let res = self.convert_expr(defs, f, expr);
+            il::ExprKind::Cdr(expr) => {
+                let res = self.convert_expr(defs, expr);
                 let tmp = gen_tmp!(self);
+                let loc = self.location(expr.span);
                 wl!(
                     self,
-                    "IC_VALUE {} = IC_cdr({});",
+                    "IC_VALUE {} = IC_cdr_at({}, {});",
                     fmt_tmp!(tmp),
-                    fmt_tmp!(res)
+                    fmt_tmp!(res),
+                    loc
+                );
+                tmp
+            }
+            il::ExprKind::Array(length, function) => {
+                let res = self.convert_expr(defs, length);
+                let tmp = gen_tmp!(self);
+                let loc = self.location(expr.span);
+                wl!(
+                    self,
+                    "IC_VALUE {} = IC_array_at({}, {}, {});",
+                    fmt_tmp!(tmp),
+                    fmt_tmp!(res),
+                    self.symbol(function),
+                    loc
+                );
+                tmp
+            }
+            il::ExprKind::Index(array, index) => {
+                let tmp = gen_tmp!(self);
+                // The array is only referenced from C once evaluated, so nothing may allocate after it
+                let index = self.convert_expr(defs, index);
+                let array = self.convert_expr(defs, array);
+                let loc = self.location(expr.span);
+                wl!(
+                    self,
+                    "IC_VALUE {} = IC_at_at({}, {}, {});",
+                    fmt_tmp!(tmp),
+                    fmt_tmp!(array),
+                    fmt_tmp!(index),
+                    loc
                 );
                 tmp
             }
+            il::ExprKind::Length(expr) => {
+                let res = self.convert_expr(defs, expr);
+                let tmp = gen_tmp!(self);
+                let loc = self.location(expr.span);
+                wl!(
+                    self,
+                    "IC_VALUE {} = IC_length_at({}, {});",
+                    fmt_tmp!(tmp),
+                    fmt_tmp!(res),
+                    loc
+                );
+                tmp
+            }
+            il::ExprKind::Record(shape, first) => {
+                let tmp = gen_tmp!(self);
+                let len = self.program.shapes[*shape].len();
+                let fields = if len == 0 {
+                    "NULL".to_string()
+                } else {
+                    let fields = (*first..first + len)
+                        .map(|i| format!("__field_{}", i))
+                        .collect::<Vec<_>>();
+                    format!("(IC_LARF[]){{{}}}", fields.join(", "))
+                };
+                wl!(
+                    self,
+                    "IC_VALUE {} = IC_record(&__shapes[{}], lar, {});",
+                    fmt_tmp!(tmp),
+                    shape,
+                    fields
+                );
+                tmp
+            }
+            il::ExprKind::Field(record, name) => {
+                let slot = self.static_slot(record, name);
+                let res = self.convert_expr(defs, record);
+                let tmp = gen_tmp!(self);
+                let loc = self.location(expr.span);
+                if let Some((shape, slot)) = slot {
+                    wl!(
+                        self,
+                        "IC_VALUE {} = IC_field_slot({}, {}, {}, \"{}\", {});",
+                        fmt_tmp!(tmp),
+                        fmt_tmp!(res),
+                        shape,
+                        slot,
+                        name,
+                        loc
+                    );
+                    return tmp;
+                }
+                wl!(
+                    self,
+                    "IC_VALUE {} = IC_field_at({}, __slots_{}, \"{}\", {});",
+                    fmt_tmp!(tmp),
+                    fmt_tmp!(res),
+                    name,
+                    name,
+                    loc
+                );
+                tmp
+            }
+            il::ExprKind::Update(record, name, i) => {
+                let slot = self.static_slot(record, name);
+                let res = self.convert_expr(defs, record);
+                let tmp = gen_tmp!(self);
+                let loc = self.location(expr.span);
+                if let Some((shape, slot)) = slot {
+                    wl!(
+                        self,
+                        "IC_VALUE {} = IC_record_update_slot({}, {}, {}, \"{}\", lar, __field_{}, {});",
+                        fmt_tmp!(tmp),
+                        fmt_tmp!(res),
+                        shape,
+                        slot,
+                        name,
+                        i,
+                        loc
+                    );
+                    return tmp;
+                }
+                wl!(
+                    self,
+                    "IC_VALUE {} = IC_record_update_at({}, __slots_{}, \"{}\", lar, __field_{}, {});",
+                    fmt_tmp!(tmp),
+                    fmt_tmp!(res),
+                    name,
+                    name,
+                    i,
+                    loc
+                );
+                tmp
+            }
+        }
+    }
+
+    // The shape and slot of a field, when they are known at compile time: the record is a literal
+    // with the field, or a single shape has it
+    fn static_slot(&self, record: &il::Expr, name: &str) -> Option<(usize, usize)> {
+        let shapes = &self.program.shapes;
+        let mut literal = record;
+        while let il::ExprKind::Update(record, _, _) = &literal.kind {
+            literal = record;
+        }
+        if let il::ExprKind::Record(shape, _) = literal.kind {
+            if let Some(slot) = shapes[shape].iter().position(|field| *field == name) {
+                return Some((shape, slot));
+            }
+        }
+
+        let mut having = (0..shapes.len()).filter(|shape| shapes[*shape].contains(&name));
+        let shape = having.next()?;
+        if having.next().is_some() {
+            return None;
+        }
+        let slot = shapes[shape].iter().position(|field| *field == name)?;
+        Some((shape, slot))
+    }
+
+    // The lhs may be a pair, array or record only referenced from C, so it is rooted while the rhs
+    // is evaluated, which may collect garbage
+    fn convert_comparison(
+        &mut self,
+        defs: &[il::Definition],
+        function: &str,
+        lhs: &il::Expr<'src>,
+        rhs: &il::Expr<'src>,
+    ) -> usize {
+        let tmp = gen_tmp!(self);
+        let lhs = self.convert_expr(defs, lhs);
+        wl!(self, "IC_root_push({});", fmt_tmp!(lhs));
+        let rhs = self.convert_expr(defs, rhs);
+        wl!(
+            self,
+            "IC_VALUE {} = {}({}, {});",
+            fmt_tmp!(tmp),
+            function,
+            fmt_tmp!(lhs),
+            fmt_tmp!(rhs)
+        );
+        wl!(self, "IC_root_pop(1);");
+        tmp
+    }
+
+    // Declares the fl location of an operation that can fail at runtime, and returns a pointer to it
+    fn location(&mut self, span: Span) -> String {
+        let loc = self.loc_cnt;
+        self.loc_cnt += 1;
+        let (path, span) = self.source.locate(span);
+        wl!(
+            self,
+            "static const IC_LOC __l{} = {{\"{}\", {}, {}, \"{}\"}};",
+            loc,
+            c_escape(path),
+            span.start.line,
+            span.start.col,
+            self.origin
+        );
+        format!("&__l{}", loc)
+    }
+
+    // Describes a definition in fl terms, thunks are named after the expression they compute
+    fn demangle(def: &il::Definition) -> String {
+        if def.is_function {
+            return def.name.clone();
+        }
+        // fl names have no underscores, so they separate the parts of a thunk's name
+        let parts = def
+            .name
+            .trim_start_matches("__")
+            .split('_')
+            .collect::<Vec<_>>();
+        match parts[..] {
+            ["car", _] => format!("car of a cons in {}", def.origin),
+            ["cdr", _] => format!("cdr of a cons in {}", def.origin),
+            ["field", _] => format!("field of a record in {}", def.origin),
+            [function, arg, _] => {
+                format!("argument {} of {}, passed in {}", arg, function, def.origin)
+            }
+            _ => def.name.clone(),
         }
     }
 
+    // The fl names of the arguments of a definition
+    fn fl_args(def: &il::Definition) -> Vec<String> {
+        let prefix = format!("__{}_", def.name);
+        def.args
+            .iter()
+            .map(|arg| arg.strip_prefix(&prefix).unwrap_or(arg).to_string())
+            .collect()
+    }
+
+    // The definition names the parameters `__a0`... as fl names may be C keywords, and the header
+    // only gives the fl names in comments
+    fn entry_point_proto(def: &il::Definition, params: &[String]) -> String {
+        let params = params
+            .iter()
+            .map(|param| format!("IC_VALUE {}", param))
+            .collect::<Vec<_>>();
+        let params = if params.is_empty() {
+            "void".to_string()
+        } else {
+            params.join(", ")
+        };
+        format!("IC_VALUE {}{}({})", LIB_PREFIX, def.name, params)
+    }
+
+    fn entry_points(&mut self) {
+        wl!(self, "void {}(void)", LIB_INIT);
+        wl!(self, "{{");
+        indent!(self);
+        wl!(
+            self,
+            "IC_atoms_init(__atom_names, {});",
+            self.program.atoms.len()
+        );
+        dedent!(self);
+        wl!(self, "}}");
+        wl!(self, "");
+
+        wl!(self, "void {}(void)", LIB_CLEANUP);
+        wl!(self, "{{");
+        indent!(self);
+        wl!(self, "IC_mem_cleanup();");
+        dedent!(self);
+        wl!(self, "}}");
+        wl!(self, "");
+
+        let defs = std::mem::take(&mut self.program.definitions);
+        for def in defs.iter().filter(|def| def.is_function) {
+            let params = (0..def.args.len())
+                .map(|i| format!("__a{}", i))
+                .collect::<Vec<_>>();
+
+            wl!(self, "{}", Self::entry_point_proto(def, &params));
+            wl!(self, "{{");
+            indent!(self);
+
+            // The arguments have to survive a collection while their LAR is allocated
+            for param in params.iter() {
+                wl!(self, "IC_root_push({});", param);
+            }
+            w!(
+                self,
+                "IC_LAR_PROTO* __lar = IC_lar_new(NULL, {}, (IC_LARF[]){{",
+                params.len()
+            );
+            write!(self.out, "{}", vec!["NULL"; params.len()].join(", ")).unwrap();
+            writeln!(self.out, "}});").unwrap();
+            for (i, param) in params.iter().enumerate() {
+                wl!(self, "IC_LAR_VALUE(__lar, {}) = {};", i, param);
+            }
+            if !params.is_empty() {
+                wl!(self, "IC_root_pop({});", params.len());
+            }
+            wl!(self, "return {}(__lar);", self.symbol(&def.name));
+
+            dedent!(self);
+            wl!(self, "}}");
+            wl!(self, "");
+        }
+        self.program.definitions = defs;
+    }
+
+    fn header(program: &il::Program) -> String {
+        let mut header = String::new();
+        header.push_str("#ifndef FL_OUT_H\n");
+        header.push_str("#define FL_OUT_H\n\n");
+        header.push_str("#include \"value.h\"\n\n");
+        header.push_str(&format!(
+            "/* Must be called before any other entry point */\nvoid {}(void);\n\n",
+            LIB_INIT
+        ));
+        header.push_str(&format!(
+            "/* Frees the whole heap, every value returned so far becomes invalid */\nvoid {}(void);\n\n",
+            LIB_CLEANUP
+        ));
+        header.push_str(
+            "/* Values returned by an entry point (and values built with IC_cons) are only kept alive\n * across further calls into the library while they are registered with IC_root_push */\n",
+        );
+        for def in program.definitions.iter().filter(|def| def.is_function) {
+            let params = Self::fl_args(def)
+                .iter()
+                .map(|name| format!("/* {} */", name))
+                .collect::<Vec<_>>();
+            header.push_str(&Self::entry_point_proto(def, &params));
+            header.push_str(";\n");
+        }
+        header.push_str("\n#endif /* FL_OUT_H */\n");
+        header
+    }
+
     fn main(&mut self) {
-        wl!(self, "int main(void)");
+        wl!(self, "int main(int argc, char** argv)");
         wl!(self, "{{");
         indent!(self);
 
-        wl!(self, "setlocale(LC_NUMERIC, \"\");");
-        wl!(self, "clock_t t1, t2;");
-        wl!(self, "t1 = clock();");
+        // In interactive mode the result is written out as it is forced, so
+        // the timing report would only get mixed into the program's output.
+        if !self.options.interact {
+            wl!(self, "setlocale(LC_NUMERIC, \"\");");
+            wl!(self, "clock_t t1, t2;");
+            wl!(self, "t1 = clock();");
+        }
+
+        wl!(
+            self,
+            "IC_atoms_init(__atom_names, {});",
+            self.program.atoms.len()
+        );
+        wl!(self, "IC_value_read_args(argc, argv);");
 
         wl!(
             self,
@@ -398,18 +1063,24 @@ impl<'src> ItoC<'src> {
         wl!(self, "IC_FUNCTION_PUSH(lar);");
         wl!(
             self,
-            "IC_VALUE res = result(IC_lar_new(lar, 0, (IC_LARF[]){{}}));"
+            "IC_VALUE res = {}(IC_lar_new(lar, 0, (IC_LARF[]){{}}));",
+            self.symbol("result")
         );
         wl!(self, "IC_LAR_VALUE(lar, 0) = res;");
-        wl!(self, "IC_value_show(res, true);");
-        wl!(self, "IC_FUNCTION_POP(lar);");
+        if self.options.interact {
+            wl!(self, "IC_value_stream(lar);");
+            wl!(self, "IC_FUNCTION_POP(lar);");
+        } else {
+            wl!(self, "IC_value_show(res, true);");
+            wl!(self, "IC_FUNCTION_POP(lar);");
 
-        wl!(self, "t2 = clock();");
-        wl!(
-            self,
-            "printf(\"c time = %.10f sec (GC: %.10f sec, Alloc: %'lu bytes)\\n\", ((double)(t2 - t1) / CLOCKS_PER_SEC),
+            wl!(self, "t2 = clock();");
+            wl!(
+                self,
+                "printf(\"c time = %.10f sec (GC: %.10f sec, Alloc: %'lu bytes)\\n\", ((double)(t2 - t1) / CLOCKS_PER_SEC),
             IC_get_gc_time(), IC_get_alloc_size());"
-        );
+            );
+        }
         wl!(self, "IC_mem_cleanup();");
         wl!(self, "return 0;");
 
@@ -418,3 +1089,16 @@ impl<'src> ItoC<'src> {
         wl!(self, "");
     }
 }
+
+fn c_escape(s: &str) -> String {
+    let mut out = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'"' => out.push_str("\\\""),
+            b'\\' => out.push_str("\\\\"),
+            b' '..=b'~' => out.push(byte as char),
+            _ => out.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    out
+}
@@ -3,14 +3,16 @@ use std::str::Chars;
 use phf::phf_map;
 
 use crate::{
-    loc::Loc,
+    diagnostic::{Code, Diagnostic, Emitter},
+    loc::{Loc, Span},
     token::{Token, TokenKind},
 };
 
-pub struct Lexer<'src> {
+pub struct Lexer<'src, 'e> {
     chars: Chars<'src>,
     start_location: Loc,
     end_location: Loc,
+    emitter: &'e Emitter<'e>,
 }
 
 pub const EOF_CHAR: char = '\0';
@@ -23,18 +25,23 @@ static KEYWORDS: phf::Map<&'static str, TokenKind> = phf_map! {
     "if" => TokenKind::If,
     "then" => TokenKind::Then,
     "else" => TokenKind::Else,
+    "extern" => TokenKind::Extern,
+    "lazy" => TokenKind::Lazy,
+    "requires" => TokenKind::Requires,
+    "ensures" => TokenKind::Ensures,
 };
 
-impl<'src> Lexer<'src> {
-    pub fn new(source: &'src str) -> Self {
+impl<'src, 'e> Lexer<'src, 'e> {
+    pub fn new(source: &'src str, emitter: &'e Emitter<'e>) -> Self {
         Self {
             chars: source.chars(),
             start_location: Loc::new(1, 1),
             end_location: Loc::new(1, 1),
+            emitter,
         }
     }
 
-    pub fn next(&mut self) -> Option<Token<'src>> {
+    pub fn next_token(&mut self) -> Option<Token<'src>> {
         self.skip_whitespace();
 
         self.start_location = self.end_location;
@@ -49,9 +56,15 @@ impl<'src> Lexer<'src> {
             '1'..='9' => Some(self.lex_num()),
             'a'..='z' | 'A'..='Z' => self.lex_alpha(),
             '\'' => self.lex_atom(),
+            '"' => self.lex_str(),
             '(' => Some(self.make_single(TokenKind::LParen)),
             ')' => Some(self.make_single(TokenKind::RParen)),
             ',' => Some(self.make_single(TokenKind::Comma)),
+            '@' => Some(self.make_single(TokenKind::At)),
+            '{' => Some(self.make_single(TokenKind::LBrace)),
+            '}' => Some(self.make_single(TokenKind::RBrace)),
+            '|' => Some(self.make_single(TokenKind::Pipe)),
+            '.' => Some(self.make_single(TokenKind::Dot)),
             '+' => Some(self.make_single(TokenKind::Add)),
             '-' => Some(self.make_single(TokenKind::Sub)),
             '*' => Some(self.make_single(TokenKind::Mul)),
@@ -66,7 +79,12 @@ impl<'src> Lexer<'src> {
                 if self.second() == '=' {
                     Some(self.make_double(TokenKind::Neq))
                 } else {
-                    self.error(&format!("Unexpected character: {}", c));
+                    // Skipped, so that the parser can carry on after it
+                    self.advance();
+                    self.error(
+                        Code::UnexpectedCharacter,
+                        format!("Unexpected character: {}", c),
+                    );
                     None
                 }
             }
@@ -85,7 +103,11 @@ impl<'src> Lexer<'src> {
                 }
             }
             _ => {
-                self.error(&format!("Unexpected character: {}", c));
+                self.advance();
+                self.error(
+                    Code::UnexpectedCharacter,
+                    format!("Unexpected character: {}", c),
+                );
                 None
             }
         }
@@ -100,7 +122,7 @@ impl<'src> Lexer<'src> {
 
         self.advance();
         let mut len = 1;
-        while !self.is_eof() && self.first().is_digit(10) {
+        while !self.is_eof() && self.first().is_ascii_digit() {
             len += 1;
             self.advance();
         }
@@ -134,7 +156,7 @@ impl<'src> Lexer<'src> {
             Some(self.make_tok(*kind))
         } else {
             if has_q {
-                self.error("Unexpected character: '?'");
+                self.error(Code::UnexpectedCharacter, "Unexpected character: '?'");
                 None
             } else {
                 Some(self.make_tok(TokenKind::Var(lexeme)))
@@ -146,7 +168,7 @@ impl<'src> Lexer<'src> {
         self.advance();
 
         if self.is_eof() || !self.first().is_alphabetic() {
-            self.error("Expected alphabetic character after '");
+            self.error(Code::InvalidAtom, "Expected alphabetic character after '");
             return None;
         }
 
@@ -164,6 +186,28 @@ impl<'src> Lexer<'src> {
         Some(self.make_tok(TokenKind::Atom(lexeme)))
     }
 
+    fn lex_str(&mut self) -> Option<Token<'src>> {
+        self.advance();
+
+        let start = self.chars.clone();
+
+        let mut len = 0;
+        while !self.is_eof() && self.first() != '"' && self.first() != '\n' {
+            len += self.first().len_utf8();
+            self.advance();
+        }
+
+        if self.first() != '"' {
+            self.error(Code::UnterminatedString, "Unterminated string");
+            return None;
+        }
+        self.advance();
+
+        let lexeme = &start.as_str()[..len];
+
+        Some(self.make_tok(TokenKind::Str(lexeme)))
+    }
+
     fn make_single(&mut self, kind: TokenKind<'src>) -> Token<'src> {
         self.advance();
         self.make_tok(kind)
@@ -176,7 +220,7 @@ impl<'src> Lexer<'src> {
     }
 
     fn make_tok(&mut self, kind: TokenKind<'src>) -> Token<'src> {
-        Token::new(kind, self.start_location)
+        Token::new(kind, Span::new(self.start_location, self.end_location))
     }
 
     fn skip_whitespace(&mut self) {
@@ -208,11 +252,10 @@ impl<'src> Lexer<'src> {
         }
     }
 
-    pub fn error<S: AsRef<str>>(&self, message: S) {
-        eprintln!(
-            "[Parse error][{}]: {}.",
-            self.end_location,
-            message.as_ref()
-        );
+    // Points at what has been read of the current token
+    fn error<S: Into<String>>(&self, code: Code, message: S) {
+        let span = Span::new(self.start_location, self.end_location);
+        self.emitter
+            .emit(Diagnostic::error(code, Some(span), message));
     }
 }
@@ -0,0 +1,271 @@
+// The ic compiler as a library. Each pass takes the output of the previous one, and none of them
+// touch the filesystem or write to the terminal: diagnostics are returned to the caller. Only
+// `build` writes files, in the build directory, and runs the C compiler. `cli` parses ic's
+// command line, for the binary.
+
+use std::path::PathBuf;
+
+use backend::Backend;
+use diagnostic::{Diagnostics, Emitter};
+use lint::Lint;
+
+pub mod backend;
+pub mod build;
+pub mod cli;
+pub mod diagnostic;
+pub mod fl;
+mod ftoh;
+pub mod hir;
+mod htoi;
+pub mod il;
+pub mod itoc;
+pub mod lexer;
+pub mod lint;
+pub mod loc;
+pub mod manifest;
+pub mod parser;
+pub mod runtime;
+pub mod token;
+
+pub use backend::Generated;
+pub use build::Options as BuildOptions;
+
+// An fl program and the path it is reported under. A program of several files is compiled as
+// their concatenation, and `files` tells where each starts, as the number of lines before it.
+#[derive(Clone, Copy, Debug)]
+pub struct Source<'src> {
+    pub path: &'src str,
+    pub text: &'src str,
+    pub files: &'src [(&'src str, usize)],
+}
+
+impl<'src> Source<'src> {
+    // A program of a single file
+    pub fn new(path: &'src str, text: &'src str) -> Self {
+        Self {
+            path,
+            text,
+            files: &[],
+        }
+    }
+
+    // The file and location within it of a span of the program
+    pub fn locate(&self, span: loc::Span) -> (&'src str, loc::Span) {
+        loc::locate(self.path, self.files, span)
+    }
+
+    fn emitter(&self) -> Emitter<'src> {
+        Emitter::collecting(self.path, self.text).with_files(self.files)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct CompileOptions {
+    // Stream the result to stdout as a list of characters
+    pub interact: bool,
+    // Generate a library with a C entry point per definition, instead of a program
+    pub emit_lib: bool,
+    // Check the requires/ensures clauses of definitions at runtime
+    pub contracts: bool,
+    // Split large programs into several C units, which are rebuilt separately
+    pub split: bool,
+    pub lints: Vec<Lint>,
+}
+
+impl Default for CompileOptions {
+    fn default() -> Self {
+        Self {
+            interact: false,
+            emit_lib: false,
+            contracts: true,
+            split: true,
+            lints: Lint::ALL.to_vec(),
+        }
+    }
+}
+
+// The generated source of a program, and the warnings about it
+#[derive(Clone, Debug)]
+pub struct Artifact {
+    pub code: Generated,
+    pub warnings: Diagnostics,
+}
+
+// An executable or library built from an artifact, and the C compiler's warnings
+#[derive(Clone, Debug)]
+pub struct Built {
+    pub output: PathBuf,
+    pub warnings: Diagnostics,
+}
+
+// Compiles a program to C, without building it
+pub fn compile(source: Source, options: &CompileOptions) -> Result<Artifact, Diagnostics> {
+    compile_with(source, options, &c_backend(source, options))
+}
+
+// Compiles a program with any backend, without building it
+pub fn compile_with(
+    source: Source,
+    options: &CompileOptions,
+    backend: &dyn Backend,
+) -> Result<Artifact, Diagnostics> {
+    let program = to_hir(source, parse(source)?, options)?;
+    let warnings = lint(source, &program, options);
+    let code = generate(backend, to_il(program), options);
+    Ok(Artifact { code, warnings })
+}
+
+pub fn tokens(source: Source) -> Result<Vec<token::Token>, Diagnostics> {
+    let emitter = source.emitter();
+    let mut lexer = lexer::Lexer::new(source.text, &emitter);
+    let mut tokens = Vec::new();
+    loop {
+        let Some(token) = lexer.next_token() else {
+            continue;
+        };
+        let eof = token.kind == token::TokenKind::Eof;
+        tokens.push(token);
+        if eof {
+            break;
+        }
+    }
+    finish(emitter, tokens)
+}
+
+pub fn parse(source: Source) -> Result<fl::Program, Diagnostics> {
+    let emitter = source.emitter();
+    let lexer = lexer::Lexer::new(source.text, &emitter);
+    let program = parser::Parser::new(lexer, &emitter).parse();
+    finish_option(emitter, program)
+}
+
+// Resolves names and checks the program, which needs a 'result' unless it is a library
+pub fn to_hir<'src>(
+    source: Source<'src>,
+    program: fl::Program<'src>,
+    options: &CompileOptions,
+) -> Result<hir::Program<'src>, Diagnostics> {
+    let emitter = source.emitter();
+    let program = ftoh::FtoH::new(program, &emitter, !options.emit_lib).convert();
+    finish_option(emitter, program)
+}
+
+// The warnings of the enabled lints
+pub fn lint(source: Source, program: &hir::Program, options: &CompileOptions) -> Diagnostics {
+    let emitter = source.emitter();
+    lint::Linter::new(program, &emitter, &options.lints, !options.emit_lib).check();
+    emitter.into_diagnostics()
+}
+
+pub fn to_il(program: hir::Program) -> il::Program {
+    htoi::HtoI::new(program).convert()
+}
+
+pub fn to_c(source: Source, program: il::Program, options: &CompileOptions) -> Generated {
+    generate(&c_backend(source, options), program, options)
+}
+
+// The C backend, generating what the options ask for
+pub fn c_backend<'src>(source: Source<'src>, options: &CompileOptions) -> itoc::CBackend<'src> {
+    itoc::CBackend::new(
+        itoc::Options {
+            interact: options.interact,
+            emit_lib: options.emit_lib,
+            contracts: options.contracts,
+        },
+        source,
+    )
+}
+
+// Generates a program with any backend, as `to_c` does with the C one
+pub fn generate(
+    backend: &dyn Backend,
+    program: il::Program,
+    options: &CompileOptions,
+) -> Generated {
+    // Writing to memory can't fail
+    let header = options.emit_lib.then(|| {
+        let mut header = Vec::new();
+        backend.header(&program, &mut header).unwrap();
+        String::from_utf8(header).unwrap()
+    });
+    let units = if options.split {
+        backend.generate_units(program)
+    } else {
+        let mut code = Vec::new();
+        backend.generate(program, &mut code).unwrap();
+        vec![String::from_utf8(code).unwrap()]
+    };
+    Generated {
+        units,
+        header,
+        extension: backend.extension(),
+        header_extension: backend.header_extension(),
+    }
+}
+
+// Identifies what a program's C is generated from: the compiler's version, the source and the
+// options. A build given it as `BuildOptions::fingerprint` records it, for `up_to_date`.
+// A rebuilt compiler of the same version may generate different C, which a caller that can tell
+// adds to it.
+pub fn fingerprint(source: Source, options: &CompileOptions) -> u64 {
+    let mut hash = runtime::fnv1a(runtime::FNV_OFFSET, env!("CARGO_PKG_VERSION").as_bytes());
+    for part in [source.path, source.text] {
+        hash = runtime::fnv1a(hash, part.as_bytes());
+        hash = runtime::fnv1a(hash, &[0]);
+    }
+    for (file, before) in source.files {
+        hash = runtime::fnv1a(hash, format!("{}\0{}\0", file, before).as_bytes());
+    }
+    runtime::fnv1a(hash, format!("{:?}", options).as_bytes())
+}
+
+// The output of an earlier build of the same fingerprint and options, when it is still there.
+// The program then needs neither generating nor building again.
+pub fn up_to_date(source: Source, options: &BuildOptions, library: bool) -> Option<PathBuf> {
+    let emitter = source.emitter();
+    let builder = build::Builder::new(options, &emitter);
+    // A runtime that can't be found is reported by the build that follows
+    let runtime = builder.runtime()?;
+    builder
+        .is_up_to_date(&runtime, library)
+        .then(|| builder.output(library))
+}
+
+// Writes the C source into the build directory and builds it with the runtime, into an
+// executable, or a library when the source has a header. Only the units whose C changed since
+// the last build in the directory are compiled again.
+pub fn build(source: Source, c: &Generated, options: &BuildOptions) -> Result<Built, Diagnostics> {
+    let emitter = source.emitter();
+    let builder = build::Builder::new(options, &emitter);
+    let built = builder
+        .runtime()
+        .is_some_and(|runtime| builder.build(c, &runtime));
+    let output = builder.output(c.header.is_some());
+    let diagnostics = emitter.into_diagnostics();
+    if built {
+        Ok(Built {
+            output,
+            warnings: diagnostics,
+        })
+    } else {
+        Err(diagnostics)
+    }
+}
+
+// Passes report errors, which are the only diagnostics outside of lints, and may still return
+// a value with them
+fn finish<T>(emitter: Emitter, value: T) -> Result<T, Diagnostics> {
+    if emitter.error_count() > 0 {
+        Err(emitter.into_diagnostics())
+    } else {
+        Ok(value)
+    }
+}
+
+fn finish_option<T>(emitter: Emitter, value: Option<T>) -> Result<T, Diagnostics> {
+    match value {
+        Some(value) => finish(emitter, value),
+        None => Err(emitter.into_diagnostics()),
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::HashMap;
+
+use crate::diagnostic::{Code, Diagnostic, Emitter};
+use crate::hir;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Lint {
+    // Definitions that 'result' never refers to, directly or not
+    UnreachableDefinition,
+    UnusedArgument,
+    // Constants whose value is needed to compute it, as in 'x = x + 1'
+    SelfRecursiveConstant,
+    // 'if' conditions that can't be an atom, let alone 'true or 'false
+    NonBooleanCondition,
+}
+
+impl Lint {
+    pub const ALL: [Lint; 4] = [
+        Lint::UnreachableDefinition,
+        Lint::UnusedArgument,
+        Lint::SelfRecursiveConstant,
+        Lint::NonBooleanCondition,
+    ];
+
+    // The name used by the -W and -A flags
+    pub fn name(self) -> &'static str {
+        match self {
+            Lint::UnreachableDefinition => "unreachable-definition",
+            Lint::UnusedArgument => "unused-argument",
+            Lint::SelfRecursiveConstant => "self-recursive-constant",
+            Lint::NonBooleanCondition => "non-boolean-condition",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Lint> {
+        Lint::ALL.into_iter().find(|lint| lint.name() == name)
+    }
+}
+
+// Warns about suspicious but valid programs, the program is compiled all the same
+pub struct Linter<'a, 'src> {
+    program: &'a hir::Program<'src>,
+    emitter: &'a Emitter<'a>,
+    enabled: &'a [Lint],
+    // Libraries have no 'result', every definition is reachable from outside
+    require_result: bool,
+    // Maps global names to their index in the program definitions array.
+    globals: HashMap<&'src str, usize>,
+}
+
+impl<'a, 'src> Linter<'a, 'src> {
+    pub fn new(
+        program: &'a hir::Program<'src>,
+        emitter: &'a Emitter<'a>,
+        enabled: &'a [Lint],
+        require_result: bool,
+    ) -> Self {
+        Self {
+            program,
+            emitter,
+            enabled,
+            require_result,
+            globals: program
+                .definitions
+                .iter()
+                .enumerate()
+                .map(|(i, def)| (def.name, i))
+                .collect(),
+        }
+    }
+
+    pub fn check(&self) {
+        if self.enabled(Lint::UnreachableDefinition) && self.require_result {
+            self.check_reachable();
+        }
+        for def in self.program.definitions.iter() {
+            if self.enabled(Lint::UnusedArgument) {
+                self.check_unused_arguments(def);
+            }
+            if self.enabled(Lint::SelfRecursiveConstant) {
+                self.check_self_recursive(def);
+            }
+            if self.enabled(Lint::NonBooleanCondition) {
+                Self::exprs(def).for_each(|expr| self.check_conditions(expr));
+            }
+        }
+    }
+
+    fn check_reachable(&self) {
+        let mut reached = vec![false; self.program.definitions.len()];
+        let mut pending = self
+            .globals
+            .get("result")
+            .copied()
+            .into_iter()
+            .collect::<Vec<_>>();
+        while let Some(i) = pending.pop() {
+            if reached[i] {
+                continue;
+            }
+            reached[i] = true;
+
+            let mut names = Vec::new();
+            Self::exprs(&self.program.definitions[i])
+                .for_each(|expr| Self::referenced(expr, &mut names));
+            pending.extend(names.into_iter().filter_map(|name| self.globals.get(name)));
+        }
+
+        for (def, reached) in self.program.definitions.iter().zip(reached) {
+            if !reached {
+                self.warn(
+                    Lint::UnreachableDefinition,
+                    Diagnostic::warning(
+                        Code::UnreachableDefinition,
+                        Some(def.span),
+                        format!("{} is never used", def.name),
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_unused_arguments(&self, def: &hir::Definition<'src>) {
+        if matches!(def.body.kind, hir::ExprKind::Extern(..)) {
+            return;
+        }
+
+        let mut locals = Vec::new();
+        Self::exprs(def).for_each(|expr| Self::locals(expr, &mut locals));
+        for (arg, span) in def.args.iter().zip(def.arg_spans.iter()) {
+            if !locals.contains(&arg.as_str()) {
+                let name = arg.strip_prefix(&format!("__{}_", def.name)).unwrap_or(arg);
+                self.warn(
+                    Lint::UnusedArgument,
+                    Diagnostic::warning(
+                        Code::UnusedArgument,
+                        Some(*span),
+                        format!("Argument {} of {} is never used", name, def.name),
+                    ),
+                );
+            }
+        }
+    }
+
+    fn check_self_recursive(&self, def: &hir::Definition<'src>) {
+        if def.args.is_empty() && self.forces(&def.body, def.name, &mut Vec::new()) {
+            self.warn(
+                Lint::SelfRecursiveConstant,
+                Diagnostic::warning(
+                    Code::SelfRecursiveConstant,
+                    Some(def.span),
+                    format!("{} needs its own value to be computed", def.name),
+                )
+                .with_note("evaluating it never terminates"),
+            );
+        }
+    }
+
+    fn check_conditions(&self, expr: &hir::Expr<'src>) {
+        if let hir::ExprKind::If(cond, _, _) = &expr.kind {
+            let boolean = match cond.kind {
+                hir::ExprKind::Atom(atom) => matches!(self.program.atoms[atom], "true" | "false"),
+                hir::ExprKind::Num(_)
+                | hir::ExprKind::Add(..)
+                | hir::ExprKind::Sub(..)
+                | hir::ExprKind::Mul(..)
+                | hir::ExprKind::Cons(..)
+                | hir::ExprKind::Array(..)
+                | hir::ExprKind::Length(_)
+                | hir::ExprKind::Record(..)
+                | hir::ExprKind::Update(..) => false,
+                _ => true,
+            };
+            if !boolean {
+                self.warn(
+                    Lint::NonBooleanCondition,
+                    Diagnostic::warning(
+                        Code::NonBooleanCondition,
+                        Some(cond.span),
+                        "if condition is never 'true or 'false",
+                    )
+                    .with_note("evaluating it is a runtime error"),
+                );
+            }
+        }
+        Self::children(expr)
+            .into_iter()
+            .for_each(|child| self.check_conditions(child));
+    }
+
+    // Whether evaluating the expression always evaluates the constant 'target',
+    // following the constants it refers to.
+    fn forces(&self, expr: &hir::Expr<'src>, target: &str, visited: &mut Vec<&'src str>) -> bool {
+        match &expr.kind {
+            hir::ExprKind::Global(name) => {
+                if *name == target {
+                    return true;
+                }
+                if visited.contains(name) {
+                    return false;
+                }
+                visited.push(name);
+                let def = &self.program.definitions[self.globals[name]];
+                def.args.is_empty() && self.forces(&def.body, target, visited)
+            }
+            hir::ExprKind::Add(lhs, rhs)
+            | hir::ExprKind::Sub(lhs, rhs)
+            | hir::ExprKind::Mul(lhs, rhs)
+            | hir::ExprKind::Eq(lhs, rhs)
+            | hir::ExprKind::Neq(lhs, rhs)
+            | hir::ExprKind::Lt(lhs, rhs)
+            | hir::ExprKind::Gt(lhs, rhs)
+            | hir::ExprKind::Le(lhs, rhs)
+            | hir::ExprKind::Ge(lhs, rhs)
+            | hir::ExprKind::Index(lhs, rhs) => {
+                self.forces(lhs, target, visited) || self.forces(rhs, target, visited)
+            }
+            hir::ExprKind::IsPair(expr)
+            | hir::ExprKind::Car(expr)
+            | hir::ExprKind::Cdr(expr)
+            | hir::ExprKind::Array(expr, _)
+            | hir::ExprKind::Length(expr)
+            | hir::ExprKind::Field(expr, _)
+            | hir::ExprKind::Update(expr, _, _, _) => self.forces(expr, target, visited),
+            // A base case is a branch that doesn't need the constant
+            hir::ExprKind::If(cond, then, els) => {
+                self.forces(cond, target, visited)
+                    || (self.forces(then, target, visited) && self.forces(els, target, visited))
+            }
+            // Arguments, list and record elements are evaluated lazily
+            _ => false,
+        }
+    }
+
+    fn referenced(expr: &hir::Expr<'src>, names: &mut Vec<&'src str>) {
+        match &expr.kind {
+            hir::ExprKind::Global(name)
+            | hir::ExprKind::Call(name, _, _)
+            | hir::ExprKind::Array(_, name) => names.push(name),
+            _ => {}
+        }
+        Self::children(expr)
+            .into_iter()
+            .for_each(|child| Self::referenced(child, names));
+    }
+
+    fn locals<'e>(expr: &'e hir::Expr<'src>, locals: &mut Vec<&'e str>) {
+        if let hir::ExprKind::Local(name) = &expr.kind {
+            locals.push(name);
+        }
+        Self::children(expr)
+            .into_iter()
+            .for_each(|child| Self::locals(child, locals));
+    }
+
+    // The body and the contract clauses of a definition
+    fn exprs<'e>(def: &'e hir::Definition<'src>) -> impl Iterator<Item = &'e hir::Expr<'src>> {
+        std::iter::once(&def.body)
+            .chain(def.requires.as_ref())
+            .chain(def.ensures.as_ref())
+    }
+
+    fn children<'e>(expr: &'e hir::Expr<'src>) -> Vec<&'e hir::Expr<'src>> {
+        match &expr.kind {
+            hir::ExprKind::Local(_)
+            | hir::ExprKind::Global(_)
+            | hir::ExprKind::Atom(_)
+            | hir::ExprKind::Num(_)
+            | hir::ExprKind::Input
+            | hir::ExprKind::ReadValue
+            | hir::ExprKind::Extern(..) => vec![],
+            hir::ExprKind::Add(lhs, rhs)
+            | hir::ExprKind::Sub(lhs, rhs)
+            | hir::ExprKind::Mul(lhs, rhs)
+            | hir::ExprKind::Eq(lhs, rhs)
+            | hir::ExprKind::Neq(lhs, rhs)
+            | hir::ExprKind::Lt(lhs, rhs)
+            | hir::ExprKind::Gt(lhs, rhs)
+            | hir::ExprKind::Le(lhs, rhs)
+            | hir::ExprKind::Ge(lhs, rhs)
+            | hir::ExprKind::Cons(lhs, rhs, _)
+            | hir::ExprKind::Index(lhs, rhs)
+            | hir::ExprKind::Update(lhs, _, rhs, _) => vec![lhs, rhs],
+            hir::ExprKind::IsPair(expr)
+            | hir::ExprKind::Car(expr)
+            | hir::ExprKind::Cdr(expr)
+            | hir::ExprKind::Array(expr, _)
+            | hir::ExprKind::Length(expr)
+            | hir::ExprKind::Field(expr, _) => vec![expr],
+            hir::ExprKind::If(cond, then, els) => vec![cond, then, els],
+            hir::ExprKind::Call(_, args, _) | hir::ExprKind::Record(_, args, _) => {
+                args.iter().collect()
+            }
+        }
+    }
+
+    fn enabled(&self, lint: Lint) -> bool {
+        self.enabled.contains(&lint)
+    }
+
+    fn warn(&self, lint: Lint, diagnostic: Diagnostic) {
+        self.emitter
+            .emit(diagnostic.with_note(format!("-W{} is on by default", lint.name())));
+    }
+}
@@ -1,6 +1,6 @@
-use std::fmt::Display;
+use std::fmt::{Debug, Display};
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
 pub struct Loc {
     pub line: usize,
     pub col: usize,
@@ -17,3 +17,55 @@ impl Display for Loc {
         write!(f, "{}:{}", self.line, self.col)
     }
 }
+
+// Kept short, since every node of a dumped tree has a span
+impl Debug for Loc {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self)
+    }
+}
+
+// The start of a token or node, and the location just past its end
+#[derive(Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: Loc,
+    pub end: Loc,
+}
+
+impl Span {
+    pub fn new(start: Loc, end: Loc) -> Self {
+        Self { start, end }
+    }
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.start)
+    }
+}
+
+impl Debug for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}-{}", self.start, self.end)
+    }
+}
+
+// Where a span of a program whose files are concatenated is, as a file and a span within it.
+// Files are given with the number of lines before them, and a program of a single file,
+// `path`, has none.
+pub fn locate<'a>(path: &'a str, files: &[(&'a str, usize)], span: Span) -> (&'a str, Span) {
+    match files
+        .iter()
+        .rev()
+        .find(|(_, before)| *before < span.start.line)
+    {
+        Some((file, before)) => (
+            file,
+            Span::new(
+                Loc::new(span.start.line - before, span.start.col),
+                Loc::new(span.end.line.saturating_sub(*before), span.end.col),
+            ),
+        ),
+        None => (path, span),
+    }
+}
@@ -1,64 +1,307 @@
-#![feature(iter_intersperse)]
-
 use std::env;
 use std::fs::read_to_string;
-use std::process::ExitCode;
-
-pub mod fl;
-mod ftoh;
-pub mod hir;
-mod htoi;
-pub mod il;
-pub mod itoc;
-pub mod lexer;
-pub mod loc;
-pub mod parser;
-pub mod token;
+use std::os::unix::process::ExitStatusExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitCode};
+
+use ic::cli::{self, Emit};
+use ic::diagnostic::{Diagnostics, ErrorFormat};
+use ic::manifest::{self, Manifest};
+use ic::runtime;
 
 fn main() -> ExitCode {
     env::set_var("RUST_BACKTRACE", "1");
     env::set_var("RUST_LIB_BACKTRACE", "0");
 
-    let args = env::args().collect::<Box<[String]>>();
-
-    if args.len() != 2 {
-        eprintln!("Source file required");
-        return ExitCode::FAILURE;
-    }
-
-    let ic_home = match env::var("IC_HOME") {
-        Ok(ic_home) => ic_home,
-        Err(e) => {
-            eprintln!("Can't get environment variable IC_HOME: {}", e);
+    let (mut args, program_args, project) = match cli::parse(env::args().skip(1)) {
+        Ok(cli::Command::Compile(args)) => (args, None, false),
+        Ok(cli::Command::Run(args, program_args)) => (args, Some(program_args), false),
+        Ok(cli::Command::Build(args)) => (args, None, true),
+        Ok(cli::Command::Help) => {
+            print!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Ok(cli::Command::Version) => {
+            println!("ic {}", env!("CARGO_PKG_VERSION"));
+            return ExitCode::SUCCESS;
+        }
+        Err(message) => {
+            eprintln!("{}", message);
+            eprintln!("Run 'ic --help' for usage");
             return ExitCode::FAILURE;
         }
     };
 
-    let source = match read_to_string(&args[1]) {
-        Ok(source) => source,
-        Err(e) => {
-            eprintln!("Failed to read source file {}: {}", &args[1], e);
+    let program = if project {
+        load_project(&mut args)
+    } else {
+        read_to_string(&args.path)
+            .map(|text| Program {
+                name: args.path.clone(),
+                text,
+                files: Vec::new(),
+            })
+            .map_err(|e| format!("Failed to read source file {}: {}", args.path, e))
+    };
+    let program = match program {
+        Ok(program) => program,
+        Err(message) => {
+            eprintln!("{}", message);
             return ExitCode::FAILURE;
         }
     };
 
-    let lexer = lexer::Lexer::new(&source);
+    let files = program
+        .files
+        .iter()
+        .map(|(file, before)| (file.as_str(), *before))
+        .collect::<Vec<_>>();
+    let source = ic::Source {
+        path: &program.name,
+        text: &program.text,
+        files: &files,
+    };
+    let format = args.error_format;
+    let options = ic::CompileOptions {
+        interact: args.interact,
+        emit_lib: args.emit_lib,
+        contracts: args.contracts,
+        // --emit=c writes a single file
+        split: args.emit == Emit::Exe,
+        lints: args.lints,
+    };
+
+    if args.emit == Emit::Tokens {
+        return match ic::tokens(source) {
+            Ok(tokens) => dump(
+                &args.output,
+                &tokens
+                    .iter()
+                    .map(|token| {
+                        format!("{}-{}\t{}\n", token.span.start, token.span.end, token.kind)
+                    })
+                    .collect::<String>(),
+            ),
+            Err(diagnostics) => fail(&diagnostics, format),
+        };
+    }
+
+    let fp = match ic::parse(source) {
+        Ok(fp) => fp,
+        Err(diagnostics) => return fail(&diagnostics, format),
+    };
+    if args.emit == Emit::Ast {
+        return dump(&args.output, &format!("{:#?}\n", fp));
+    }
+
+    let hir = match ic::to_hir(source, fp, &options) {
+        Ok(hir) => hir,
+        Err(diagnostics) => return fail(&diagnostics, format),
+    };
+
+    ic::lint(source, &hir, &options).report(format);
+    if args.emit == Emit::Hir {
+        return dump(&args.output, &format!("{:#?}\n", hir));
+    }
+
+    let il = ic::to_il(hir);
+    if args.emit == Emit::Il {
+        return dump(&args.output, &format!("{:#?}\n", il));
+    }
+
+    if args.emit == Emit::C {
+        let c = ic::to_c(source, il, &options);
+        // A library's header goes next to its C source, when that is a file
+        if let (Some(header), Some(output)) = (&c.header, &args.output) {
+            let code = dump(&Some(output.with_extension(c.header_extension)), header);
+            if code != ExitCode::SUCCESS {
+                return code;
+            }
+        }
+        return dump(&args.output, &c.units.concat());
+    }
+
+    // 'ic run' keeps its builds out of the way, in a directory per program that later runs reuse.
+    // IC_HOME overrides the runtime built into the compiler with that of an ic repository.
+    let build_options = ic::BuildOptions {
+        link: args.link,
+        build_dir: match args.build_dir {
+            Some(build_dir) => build_dir,
+            None if program_args.is_some() => run_dir(&args.path),
+            None => PathBuf::from("_build"),
+        },
+        output: args.output,
+        debug: args.debug,
+        debug_info: args.debug_info,
+        cc: args
+            .cc
+            .or_else(|| env::var("CC").ok().filter(|cc| !cc.trim().is_empty()))
+            .unwrap_or_else(|| "gcc".to_string()),
+        cflags: args.cflags,
+        ldflags: args.ldflags,
+        sanitize: args.sanitize,
+        ic_home: env::var_os("IC_HOME").map(PathBuf::from),
+        fingerprint: Some(compiler_stamp(ic::fingerprint(source, &options))),
+    };
+    // An unchanged program isn't generated or built again
+    let exe = match ic::up_to_date(source, &build_options, options.emit_lib) {
+        Some(output) => output,
+        None => match ic::build(source, &ic::to_c(source, il, &options), &build_options) {
+            Ok(built) => {
+                built.warnings.report(format);
+                built.output
+            }
+            Err(diagnostics) => return fail(&diagnostics, format),
+        },
+    };
+
+    match program_args {
+        Some(program_args) => run(&exe, &program_args),
+        None => ExitCode::SUCCESS,
+    }
+}
+
+// The sources of what is compiled, a single file or the files of a project, concatenated
+struct Program {
+    name: String,
+    text: String,
+    files: Vec<(String, usize)>,
+}
+
+// Reads the project of the closest ic.toml, whose package and profile fill in the options that
+// the command line doesn't give
+fn load_project(args: &mut cli::Args) -> Result<Program, String> {
+    let cwd =
+        env::current_dir().map_err(|e| format!("Failed to find the current directory: {}", e))?;
+    let dir = cwd.join(&args.path);
+    let Some(path) = Manifest::find(&dir) else {
+        return Err(format!(
+            "No {} in {} or its parents",
+            manifest::FILE_NAME,
+            dir.display()
+        ));
+    };
+    let root = path.parent().unwrap();
+    // Paths are shown relative to the current directory when they can be
+    let shown = |path: &Path| {
+        path.strip_prefix(&cwd)
+            .unwrap_or(path)
+            .display()
+            .to_string()
+    };
 
-    let mut parser = parser::Parser::new(lexer);
-    let Some(fp) = parser.parse() else {
-        return ExitCode::FAILURE;
+    let text =
+        read_to_string(&path).map_err(|e| format!("Failed to read {}: {}", shown(&path), e))?;
+    let manifest = Manifest::parse(&shown(&path), &text)?;
+    let name = args.profile.as_deref().unwrap_or(manifest::DEFAULT_PROFILE);
+    let Some(profile) = manifest.profile(name) else {
+        return Err(format!("{} has no profile {}", shown(&path), name));
     };
 
-    let ftoh = ftoh::FtoH::new(fp);
-    let Some(hir) = ftoh.convert() else {
-        return ExitCode::FAILURE;
+    args.emit_lib = manifest.lib;
+    args.contracts &= profile.contracts;
+    args.debug = profile.debug;
+    args.debug_info |= profile.debug_info;
+    args.cflags.splice(0..0, profile.cflags.iter().cloned());
+    args.ldflags.splice(0..0, profile.ldflags.iter().cloned());
+    args.sanitize = args.sanitize.take().or_else(|| profile.sanitize.clone());
+    // Files and directories on the link line are relative to the project
+    let link = manifest
+        .link
+        .iter()
+        .map(|arg| match arg.strip_prefix("-L") {
+            Some(dir) => format!("-L{}", root.join(dir).display()),
+            None if arg.starts_with('-') => arg.clone(),
+            None => root.join(arg).display().to_string(),
+        });
+    args.link.splice(0..0, link);
+    let build_dir = root.join("_build").join(&profile.name);
+    if args.output.is_none() && args.emit == Emit::Exe {
+        args.output = Some(build_dir.join(if manifest.lib {
+            format!("lib{}.a", manifest.name)
+        } else {
+            manifest.name.clone()
+        }));
+    }
+    args.build_dir.get_or_insert(build_dir);
+
+    let mut program = Program {
+        name: manifest.name.clone(),
+        text: String::new(),
+        files: Vec::new(),
     };
+    let files = manifest
+        .files(root)
+        .map_err(|e| format!("Failed to list the sources of {}: {}", shown(&path), e))?;
+    for file in files {
+        let text = read_to_string(&file)
+            .map_err(|e| format!("Failed to read source file {}: {}", shown(&file), e))?;
+        program
+            .files
+            .push((shown(&file), program.text.lines().count()));
+        program.text.push_str(&text);
+        if !text.ends_with('\n') {
+            program.text.push('\n');
+        }
+    }
+    Ok(program)
+}
 
-    let htoi = htoi::HtoI::new(hir);
-    let il = htoi.convert();
+// Adds this executable's stamp to a program's fingerprint, as a rebuilt compiler of the same
+// version may generate different C
+fn compiler_stamp(fingerprint: u64) -> u64 {
+    match env::current_exe() {
+        Ok(compiler) => ic::build::file_stamp(fingerprint, &compiler),
+        Err(_) => fingerprint,
+    }
+}
+
+fn run_dir(path: &str) -> PathBuf {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path));
+    let key = runtime::fnv1a(runtime::FNV_OFFSET, path.as_os_str().as_encoded_bytes());
+    runtime::cache_dir()
+        .unwrap_or_else(env::temp_dir)
+        .join(format!("run-{:016x}", key))
+}
+
+// Runs the program with the terminal's stdio, and exits as it did
+fn run(exe: &Path, args: &[String]) -> ExitCode {
+    // A bare name would be looked up in PATH
+    let exe = if exe.is_relative() {
+        Path::new(".").join(exe)
+    } else {
+        exe.to_path_buf()
+    };
+    let status = match Command::new(&exe).args(args).status() {
+        Ok(status) => status,
+        Err(e) => {
+            eprintln!("Failed to run {}: {}", exe.display(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Killed by a signal, reported the way shells do
+    let code = status
+        .code()
+        .or_else(|| status.signal().map(|signal| 128 + signal))
+        .unwrap_or(1);
+    ExitCode::from(code as u8)
+}
 
-    let itoc = itoc::ItoC::new(il, ic_home);
-    itoc.generate();
+fn fail(diagnostics: &Diagnostics, format: ErrorFormat) -> ExitCode {
+    diagnostics.report(format);
+    ExitCode::FAILURE
+}
 
+// Writes the output of an intermediate stage to a file, or to stdout
+fn dump(output: &Option<PathBuf>, text: &str) -> ExitCode {
+    match output {
+        Some(path) => {
+            if let Err(e) = std::fs::write(path, text) {
+                eprintln!("Failed to write {}: {}", path.display(), e);
+                return ExitCode::FAILURE;
+            }
+        }
+        None => print!("{}", text),
+    }
     ExitCode::SUCCESS
 }
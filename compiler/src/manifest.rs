@@ -0,0 +1,316 @@
+use std::path::{Path, PathBuf};
+
+// The ic.toml of a project, which 'ic build' compiles without a long command line:
+//
+//     [package]
+//     name = "treesort"
+//     entry = "src/main.fl"
+//     sources = ["src"]
+//     link = ["c/hash.c", "-lm"]
+//
+//     [profile.release]
+//     cflags = ["-march=native"]
+//
+// Only the part of TOML that manifests need is read: tables, and strings, booleans and arrays of
+// strings as values.
+#[derive(Clone, Debug)]
+pub struct Manifest {
+    pub name: String,
+    // The file that defines 'result', relative to the manifest's directory
+    pub entry: PathBuf,
+    // Directories whose .fl files, at any depth, are part of the program along with the entry
+    pub sources: Vec<PathBuf>,
+    // C sources, objects and libraries, and -l and -L flags, for the link line
+    pub link: Vec<String>,
+    // Build a library with a C entry point per definition, instead of an executable
+    pub lib: bool,
+    pub profiles: Vec<Profile>,
+}
+
+// How a build is configured, chosen by name with 'ic build --profile'
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    // Build the C code unoptimised and with debug information
+    pub debug: bool,
+    // Build the C code with debug information, whatever the optimisation level
+    pub debug_info: bool,
+    pub contracts: bool,
+    pub cflags: Vec<String>,
+    pub ldflags: Vec<String>,
+    pub sanitize: Option<String>,
+}
+
+impl Profile {
+    fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            debug: false,
+            debug_info: false,
+            contracts: true,
+            cflags: Vec::new(),
+            ldflags: Vec::new(),
+            sanitize: None,
+        }
+    }
+}
+
+pub const FILE_NAME: &str = "ic.toml";
+
+// 'ic build' uses dev unless told otherwise
+pub const DEFAULT_PROFILE: &str = "dev";
+
+enum Value {
+    String(String),
+    Bool(bool),
+    Array(Vec<String>),
+}
+
+impl Manifest {
+    // The manifest in a directory or the closest of its parents
+    pub fn find(dir: &Path) -> Option<PathBuf> {
+        dir.ancestors()
+            .map(|dir| dir.join(FILE_NAME))
+            .find(|path| path.is_file())
+    }
+
+    // Errors are prefixed with the path and line, as in `ic.toml:3: ...`
+    pub fn parse(path: &str, text: &str) -> Result<Self, String> {
+        let mut name = None;
+        let mut entry = None;
+        let mut sources = Vec::new();
+        let mut link = Vec::new();
+        let mut lib = false;
+        // dev, release and profile always exist, tables of the same name change them
+        let mut profiles = vec![
+            Profile {
+                debug: true,
+                ..Profile::new("dev")
+            },
+            Profile::new("release"),
+            Profile {
+                debug_info: true,
+                ..Profile::new("profile")
+            },
+        ];
+
+        let mut table = String::new();
+        let mut lines = text.lines().enumerate();
+        while let Some((i, line)) = lines.next() {
+            let error = |message: String| format!("{}:{}: {}", path, i + 1, message);
+            let mut line = strip_comment(line).trim().to_string();
+            if line.is_empty() {
+                continue;
+            }
+
+            if let Some(header) = line.strip_prefix('[') {
+                let Some(header) = header.strip_suffix(']') else {
+                    return Err(error(format!("unterminated table header: {}", line)));
+                };
+                table = header.trim().to_string();
+                if table == "package" {
+                    continue;
+                }
+                match table.strip_prefix("profile.") {
+                    Some(profile) if is_key(profile) => {
+                        if !profiles.iter().any(|p| p.name == profile) {
+                            profiles.push(Profile::new(profile));
+                        }
+                    }
+                    _ => return Err(error(format!("unknown table: [{}]", table))),
+                }
+                continue;
+            }
+
+            // Arrays may span several lines
+            while line.contains('[') && !line.contains(']') {
+                let Some((_, next)) = lines.next() else {
+                    return Err(error("unterminated array".to_string()));
+                };
+                line.push(' ');
+                line.push_str(strip_comment(next).trim());
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(error(format!("expected 'key = value': {}", line)));
+            };
+            let key = key.trim();
+            if !is_key(key) {
+                return Err(error(format!("invalid key: {}", key)));
+            }
+            let value = parse_value(value.trim()).map_err(error)?;
+            let unknown = || error(format!("unknown key '{}' in [{}]", key, table));
+            let mismatch = |expected: &str| error(format!("'{}' must be {}", key, expected));
+
+            if table.is_empty() {
+                return Err(error(format!(
+                    "'{}' must be in a table, such as [package]",
+                    key
+                )));
+            } else if table == "package" {
+                match (key, value) {
+                    ("name", Value::String(value)) => name = Some(value),
+                    ("entry", Value::String(value)) => entry = Some(PathBuf::from(value)),
+                    ("sources", Value::Array(value)) => {
+                        sources = value.into_iter().map(PathBuf::from).collect()
+                    }
+                    ("link", Value::Array(value)) => link = value,
+                    ("kind", Value::String(value)) => match value.as_str() {
+                        "bin" => lib = false,
+                        "lib" => lib = true,
+                        _ => return Err(mismatch("\"bin\" or \"lib\"")),
+                    },
+                    ("name" | "entry" | "kind", _) => return Err(mismatch("a string")),
+                    ("sources" | "link", _) => return Err(mismatch("an array of strings")),
+                    _ => return Err(unknown()),
+                }
+            } else {
+                let profile = profiles
+                    .iter_mut()
+                    .find(|profile| table.strip_prefix("profile.") == Some(&profile.name))
+                    .unwrap();
+                match (key, value) {
+                    ("debug", Value::Bool(value)) => profile.debug = value,
+                    ("debug-info", Value::Bool(value)) => profile.debug_info = value,
+                    ("contracts", Value::Bool(value)) => profile.contracts = value,
+                    ("cflags", Value::Array(value)) => profile.cflags = value,
+                    ("ldflags", Value::Array(value)) => profile.ldflags = value,
+                    ("sanitize", Value::String(value)) => profile.sanitize = Some(value),
+                    ("debug" | "debug-info" | "contracts", _) => {
+                        return Err(mismatch("true or false"))
+                    }
+                    ("cflags" | "ldflags", _) => return Err(mismatch("an array of strings")),
+                    ("sanitize", _) => return Err(mismatch("a string")),
+                    _ => return Err(unknown()),
+                }
+            }
+        }
+
+        let Some(name) = name else {
+            return Err(format!("{}: [package] has no name", path));
+        };
+        let Some(entry) = entry else {
+            return Err(format!("{}: [package] has no entry", path));
+        };
+        Ok(Self {
+            name,
+            entry,
+            sources,
+            link,
+            lib,
+            profiles,
+        })
+    }
+
+    pub fn profile(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    // The files of the program: the entry, then those of the source directories in order of
+    // their paths. Paths are under `root`, the manifest's directory.
+    pub fn files(&self, root: &Path) -> std::io::Result<Vec<PathBuf>> {
+        let entry = root.join(&self.entry);
+        let mut files = vec![entry.clone()];
+        for dir in self.sources.iter() {
+            let mut found = Vec::new();
+            fl_files(&root.join(dir), &mut found)?;
+            found.sort();
+            for file in found {
+                if !files.contains(&file) {
+                    files.push(file);
+                }
+            }
+        }
+        Ok(files)
+    }
+}
+
+fn fl_files(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            fl_files(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "fl") {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_key(key: &str) -> bool {
+    !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+// Comments start with '#' outside of strings
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+fn parse_value(value: &str) -> Result<Value, String> {
+    match value {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        _ => {}
+    }
+    if let Some(items) = value.strip_prefix('[') {
+        let Some(mut items) = items.strip_suffix(']').map(str::trim) else {
+            return Err(format!("expected ']' at the end of {}", value));
+        };
+        let mut array = Vec::new();
+        while !items.is_empty() {
+            let (item, rest) = parse_string(items)?;
+            array.push(item);
+            items = rest.trim_start();
+            // A trailing comma is allowed
+            if let Some(rest) = items.strip_prefix(',') {
+                items = rest.trim_start();
+            } else if !items.is_empty() {
+                return Err(format!("expected ',' between the items of {}", value));
+            }
+        }
+        return Ok(Value::Array(array));
+    }
+    match parse_string(value)? {
+        (string, "") => Ok(Value::String(string)),
+        (_, rest) => Err(format!("unexpected text after a string: {}", rest)),
+    }
+}
+
+// A basic string, and the text after it
+fn parse_string(text: &str) -> Result<(String, &str), String> {
+    let Some(body) = text.strip_prefix('"') else {
+        return Err(format!(
+            "expected a string, true, false or an array: {}",
+            text
+        ));
+    };
+    let mut string = String::new();
+    let mut chars = body.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Ok((string, body[i + 1..].trim_end())),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => string.push('\n'),
+                Some((_, 't')) => string.push('\t'),
+                Some((_, '"')) => string.push('"'),
+                Some((_, '\\')) => string.push('\\'),
+                _ => return Err(format!("invalid escape in {}", text)),
+            },
+            c => string.push(c),
+        }
+    }
+    Err(format!("unterminated string: {}", text))
+}
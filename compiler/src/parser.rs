@@ -1,15 +1,19 @@
 use std::collections::HashSet;
 
 use crate::{
+    diagnostic::{Code, Diagnostic, Emitter},
     fl,
     lexer::Lexer,
-    loc::Loc,
+    loc::{Loc, Span},
     token::{Token, TokenKind},
 };
 
-pub struct Parser<'src> {
-    lexer: Lexer<'src>,
+pub struct Parser<'src, 'e> {
+    lexer: Lexer<'src, 'e>,
     curr: Token<'src>,
+    // Where the last token consumed ends, which is where a node parsed up to it ends
+    prev_end: Loc,
+    emitter: &'e Emitter<'e>,
 }
 
 #[derive(PartialEq)]
@@ -18,86 +22,227 @@ enum Assoc {
     _Right,
 }
 
-impl<'src> Parser<'src> {
-    pub fn new(lexer: Lexer<'src>) -> Self {
+impl<'src, 'e> Parser<'src, 'e> {
+    pub fn new(lexer: Lexer<'src, 'e>, emitter: &'e Emitter<'e>) -> Self {
         Self {
             lexer,
-            curr: Token::new(TokenKind::Eof, Loc::new(0, 0)),
+            curr: Token::new(TokenKind::Eof, Span::new(Loc::new(0, 0), Loc::new(0, 0))),
+            prev_end: Loc::new(0, 0),
+            emitter,
         }
     }
 
+    // Every error is reported, the program is only returned if there were none
     pub fn parse(&mut self) -> Option<fl::Program<'src>> {
-        self.advance()?;
+        let errors = self.emitter.error_count();
+        while self.advance().is_none() {}
 
         let mut defs = Vec::new();
-        while !self.lexer.is_eof() {
-            let def = self.parse_def()?;
-            defs.push(def);
+        while self.curr.kind != TokenKind::Eof {
+            let start = self.curr.span.start;
+            match self.parse_def() {
+                Some(def) => defs.push(def),
+                None => self.synchronize(start),
+            }
+        }
+
+        if self.emitter.error_count() > errors {
+            None
+        } else {
+            Some(defs.into_boxed_slice())
+        }
+    }
+
+    // Skips to the next token that can start a definition at the beginning of a line.
+    // A definition that failed where it started is skipped past, so that parsing progresses.
+    fn synchronize(&mut self, failed_at: Loc) {
+        if self.curr.span.start == failed_at {
+            while self.advance().is_none() {}
+        }
+        loop {
+            let starts_def = matches!(
+                self.curr.kind,
+                TokenKind::Var(_) | TokenKind::Extern | TokenKind::At
+            );
+            if self.curr.kind == TokenKind::Eof || (starts_def && self.curr.span.start.col == 1) {
+                return;
+            }
+            // Lexical errors are reported and skipped like any other token
+            while self.advance().is_none() {}
         }
-        Some(defs.into_boxed_slice())
     }
 
     fn parse_def(&mut self) -> Option<fl::Definition<'src>> {
+        let mut memo = false;
+        while self.curr.kind == TokenKind::At {
+            self.advance()?;
+            match self.curr.kind {
+                TokenKind::Var("memo") => memo = true,
+                _ => {
+                    self.emitter.emit(
+                        Diagnostic::error(
+                            Code::UnknownAnnotation,
+                            Some(self.curr.span),
+                            format!("Unknown annotation {}", self.curr.kind),
+                        )
+                        .with_help("the only annotation is '@memo'"),
+                    );
+                    return None;
+                }
+            }
+            self.advance()?;
+        }
+
+        let mut def = if self.curr.kind == TokenKind::Extern {
+            self.parse_extern()?
+        } else {
+            self.parse_fun()?
+        };
+        def.memo = memo;
+        Some(def)
+    }
+
+    fn parse_fun(&mut self) -> Option<fl::Definition<'src>> {
+        let start = self.curr.span.start;
         let name = self.parse_var()?;
 
-        match self.curr.kind {
-            TokenKind::Equals => {
+        let mut args = Vec::new();
+        let mut arg_spans = Vec::new();
+        if self.curr.kind == TokenKind::LParen {
+            self.advance()?;
+            let mut arg_set = HashSet::new();
+            while !self.lexer.is_eof() {
+                let arg_span = self.curr.span;
+                let arg = self.parse_var()?;
+                args.push(arg);
+                arg_spans.push(arg_span);
+
+                if arg_set.contains(&arg) {
+                    self.duplicate_argument(arg, arg_span);
+                }
+                arg_set.insert(arg);
+
+                if self.curr.kind == TokenKind::RParen {
+                    break;
+                } else if self.curr.kind != TokenKind::Comma {
+                    self.error(format!("Expected ',' or ')', got {}", self.curr.kind));
+                    return None;
+                }
                 self.advance()?;
-                let expr = self.parse_expr(0)?;
-                Some(fl::Definition::new(name, Box::new([]), expr))
             }
-            TokenKind::LParen => {
-                self.advance()?;
-                let mut args = Vec::new();
-                let mut arg_set = HashSet::new();
-                while !self.lexer.is_eof() {
-                    let arg = self.parse_var()?;
-                    args.push(arg);
-
-                    if arg_set.contains(&arg) {
-                        self.error(format!("duplicate argument '{}'", arg));
-                    }
-                    arg_set.insert(arg);
 
-                    if self.curr.kind == TokenKind::RParen {
-                        break;
-                    } else if self.curr.kind != TokenKind::Comma {
-                        self.error(&format!("Expected ',' or ')', got {}", self.curr.kind));
-                        return None;
-                    }
+            self.expect(TokenKind::RParen)?;
+        }
+
+        let mut requires = None;
+        let mut ensures = None;
+        loop {
+            match self.curr.kind {
+                TokenKind::Requires if requires.is_none() => {
+                    self.advance()?;
+                    requires = Some(self.parse_expr(0)?);
+                }
+                TokenKind::Ensures if ensures.is_none() => {
                     self.advance()?;
+                    ensures = Some(self.parse_expr(0)?);
                 }
+                _ => break,
+            }
+        }
 
-                self.expect(TokenKind::RParen)?;
+        self.expect(TokenKind::Equals)?;
 
-                self.expect(TokenKind::Equals)?;
+        let expr = self.parse_expr(0)?;
 
-                let expr = self.parse_expr(0)?;
+        Some(fl::Definition::new(
+            name,
+            Span::new(start, self.prev_end),
+            args.into_boxed_slice(),
+            arg_spans.into_boxed_slice(),
+            expr,
+            requires,
+            ensures,
+        ))
+    }
 
-                Some(fl::Definition::new(name, args.into_boxed_slice(), expr))
-            }
-            _ => {
-                self.error(&format!("Expected '=', got {}", self.curr.kind));
-                None
+    // extern name(x, lazy y) = "c_function"
+    fn parse_extern(&mut self) -> Option<fl::Definition<'src>> {
+        let start = self.curr.span.start;
+        self.advance()?;
+        let name = self.parse_var()?;
+
+        let mut args = Vec::new();
+        let mut arg_spans = Vec::new();
+        let mut lazy = Vec::new();
+        if self.curr.kind == TokenKind::LParen {
+            self.advance()?;
+            while !self.lexer.is_eof() {
+                if self.curr.kind == TokenKind::Lazy {
+                    self.advance()?;
+                    lazy.push(true);
+                } else {
+                    lazy.push(false);
+                }
+
+                let arg_span = self.curr.span;
+                let arg = self.parse_var()?;
+                if args.contains(&arg) {
+                    self.duplicate_argument(arg, arg_span);
+                }
+                args.push(arg);
+                arg_spans.push(arg_span);
+
+                if self.curr.kind == TokenKind::RParen {
+                    break;
+                } else if self.curr.kind != TokenKind::Comma {
+                    self.error(format!("Expected ',' or ')', got {}", self.curr.kind));
+                    return None;
+                }
+                self.advance()?;
             }
+
+            self.expect(TokenKind::RParen)?;
         }
+
+        self.expect(TokenKind::Equals)?;
+
+        let TokenKind::Str(symbol) = self.curr.kind else {
+            self.error(format!("Expected C function name, got {}", self.curr.kind));
+            return None;
+        };
+        let symbol_span = self.curr.span;
+        self.advance()?;
+
+        Some(fl::Definition::new(
+            name,
+            Span::new(start, self.prev_end),
+            args.into_boxed_slice(),
+            arg_spans.into_boxed_slice(),
+            fl::Expr::new(
+                fl::ExprKind::Extern(symbol, lazy.into_boxed_slice()),
+                symbol_span,
+            ),
+            None,
+            None,
+        ))
     }
 
     fn parse_expr(&mut self, min_prec: u8) -> Option<fl::Expr<'src>> {
-        let mut lhs = match self.curr.kind {
+        let start = self.curr.span.start;
+        let kind = match self.curr.kind {
             TokenKind::LParen => {
                 self.advance()?;
                 let expr = self.parse_expr(0)?;
                 self.expect(TokenKind::RParen)?;
-                expr
+                expr.kind
             }
             TokenKind::Atom(name) => {
                 self.advance()?;
-                fl::Expr::Atom(name)
+                fl::ExprKind::Atom(name)
             }
             TokenKind::Num(num) => {
                 self.advance()?;
-                fl::Expr::Num(num)
+                fl::ExprKind::Num(num)
             }
             TokenKind::Var(name) => {
                 self.advance()?;
@@ -111,7 +256,7 @@ impl<'src> Parser<'src> {
                         if self.curr.kind == TokenKind::RParen {
                             break;
                         } else if self.curr.kind != TokenKind::Comma {
-                            self.error(&format!("Expected ',' or ')', got {}", self.curr.kind));
+                            self.error(format!("Expected ',' or ')', got {}", self.curr.kind));
                             return None;
                         }
                         self.advance()?;
@@ -119,9 +264,9 @@ impl<'src> Parser<'src> {
 
                     self.expect(TokenKind::RParen)?;
 
-                    fl::Expr::Call(name, args.into_boxed_slice())
+                    fl::ExprKind::Call(name, args.into_boxed_slice())
                 } else {
-                    fl::Expr::Var(name)
+                    fl::ExprKind::Var(name)
                 }
             }
             TokenKind::Cons => {
@@ -131,28 +276,28 @@ impl<'src> Parser<'src> {
                 self.expect(TokenKind::Comma)?;
                 let tail = self.parse_expr(0)?;
                 self.expect(TokenKind::RParen)?;
-                fl::Expr::Cons(Box::new(head), Box::new(tail))
+                fl::ExprKind::Cons(Box::new(head), Box::new(tail))
             }
             TokenKind::Car => {
                 self.advance()?;
                 self.expect(TokenKind::LParen)?;
                 let expr = self.parse_expr(0)?;
                 self.expect(TokenKind::RParen)?;
-                fl::Expr::Car(Box::new(expr))
+                fl::ExprKind::Car(Box::new(expr))
             }
             TokenKind::Cdr => {
                 self.advance()?;
                 self.expect(TokenKind::LParen)?;
                 let expr = self.parse_expr(0)?;
                 self.expect(TokenKind::RParen)?;
-                fl::Expr::Cdr(Box::new(expr))
+                fl::ExprKind::Cdr(Box::new(expr))
             }
             TokenKind::PairQ => {
                 self.advance()?;
                 self.expect(TokenKind::LParen)?;
                 let expr = self.parse_expr(0)?;
                 self.expect(TokenKind::RParen)?;
-                fl::Expr::IsPair(Box::new(expr))
+                fl::ExprKind::IsPair(Box::new(expr))
             }
             TokenKind::If => {
                 self.advance()?;
@@ -161,13 +306,49 @@ impl<'src> Parser<'src> {
                 let then = self.parse_expr(0)?;
                 self.expect(TokenKind::Else)?;
                 let els = self.parse_expr(0)?;
-                fl::Expr::If(Box::new(cond), Box::new(then), Box::new(els))
+                fl::ExprKind::If(Box::new(cond), Box::new(then), Box::new(els))
+            }
+            TokenKind::LBrace => {
+                self.advance()?;
+                if self.curr.kind == TokenKind::RBrace {
+                    self.advance()?;
+                    fl::ExprKind::Record(Box::new([]))
+                } else {
+                    let first = self.parse_expr(0)?;
+                    if self.curr.kind == TokenKind::Pipe {
+                        self.advance()?;
+                        let fields = self.parse_fields(Vec::new())?;
+                        fl::ExprKind::Update(Box::new(first), fields)
+                    } else {
+                        let fl::ExprKind::Var(name) = first.kind else {
+                            self.emitter.emit(Diagnostic::error(
+                                Code::UnexpectedToken,
+                                Some(first.span),
+                                "Expected field name",
+                            ));
+                            return None;
+                        };
+                        self.expect(TokenKind::Equals)?;
+                        let value = self.parse_expr(0)?;
+                        fl::ExprKind::Record(self.parse_fields(vec![(name, value)])?)
+                    }
+                }
             }
             t => {
-                self.error(&format!("Expected expression, got {}", t));
+                self.error(format!("Expected expression, got {}", t));
                 return None;
             }
         };
+        let mut lhs = fl::Expr::new(kind, Span::new(start, self.prev_end));
+
+        while self.curr.kind == TokenKind::Dot {
+            self.advance()?;
+            let field = self.parse_var()?;
+            lhs = fl::Expr::new(
+                fl::ExprKind::Field(Box::new(lhs), field),
+                Span::new(start, self.prev_end),
+            );
+        }
 
         while !self.lexer.is_eof() && Self::is_infix_op(self.curr.kind) {
             let op = self.curr.kind;
@@ -216,27 +397,46 @@ impl<'src> Parser<'src> {
     }
 
     fn make_infix(op: TokenKind, lhs: fl::Expr<'src>, rhs: fl::Expr<'src>) -> fl::Expr<'src> {
+        let span = Span::new(lhs.span.start, rhs.span.end);
         let l = Box::new(lhs);
         let r = Box::new(rhs);
-        match op {
-            TokenKind::Add => fl::Expr::Add(l, r),
-            TokenKind::Sub => fl::Expr::Sub(l, r),
-            TokenKind::Mul => fl::Expr::Mul(l, r),
-            TokenKind::Eq => fl::Expr::Eq(l, r),
-            TokenKind::Neq => fl::Expr::Neq(l, r),
-            TokenKind::Lt => fl::Expr::Lt(l, r),
-            TokenKind::Le => fl::Expr::Le(l, r),
-            TokenKind::Gt => fl::Expr::Gt(l, r),
-            TokenKind::Ge => fl::Expr::Ge(l, r),
+        let kind = match op {
+            TokenKind::Add => fl::ExprKind::Add(l, r),
+            TokenKind::Sub => fl::ExprKind::Sub(l, r),
+            TokenKind::Mul => fl::ExprKind::Mul(l, r),
+            TokenKind::Eq => fl::ExprKind::Eq(l, r),
+            TokenKind::Neq => fl::ExprKind::Neq(l, r),
+            TokenKind::Lt => fl::ExprKind::Lt(l, r),
+            TokenKind::Le => fl::ExprKind::Le(l, r),
+            TokenKind::Gt => fl::ExprKind::Gt(l, r),
+            TokenKind::Ge => fl::ExprKind::Ge(l, r),
             _ => unreachable!(),
+        };
+        fl::Expr::new(kind, span)
+    }
+
+    // Parses the remaining 'name = expr' fields of a record, up to the closing brace
+    fn parse_fields(
+        &mut self,
+        mut fields: Vec<(&'src str, fl::Expr<'src>)>,
+    ) -> Option<Box<[(&'src str, fl::Expr<'src>)]>> {
+        while self.curr.kind != TokenKind::RBrace {
+            if !fields.is_empty() {
+                self.expect(TokenKind::Comma)?;
+            }
+            let name = self.parse_var()?;
+            self.expect(TokenKind::Equals)?;
+            fields.push((name, self.parse_expr(0)?));
         }
+        self.advance()?;
+        Some(fields.into_boxed_slice())
     }
 
     fn parse_var(&mut self) -> Option<&'src str> {
-        match self.curr.kind.clone() {
+        match self.curr.kind {
             TokenKind::Var(name) => {
                 self.advance()?;
-                Some(name.clone())
+                Some(name)
             }
             _ => {
                 self.error("Expected variable");
@@ -250,17 +450,31 @@ impl<'src> Parser<'src> {
             self.advance()?;
             Some(())
         } else {
-            self.error(&format!("Expected {}, got {}", kind, self.curr.kind));
+            self.error(format!("Expected {}, got {}", kind, self.curr.kind));
             None
         }
     }
 
     fn advance(&mut self) -> Option<()> {
-        self.curr = self.lexer.next()?;
+        self.prev_end = self.curr.span.end;
+        self.curr = self.lexer.next_token()?;
         Some(())
     }
 
-    fn error<S: AsRef<str>>(&self, message: S) {
-        self.lexer.error(message);
+    // Syntax errors are reported at the token that doesn't fit
+    fn error<S: Into<String>>(&self, message: S) {
+        self.emitter.emit(Diagnostic::error(
+            Code::UnexpectedToken,
+            Some(self.curr.span),
+            message,
+        ));
+    }
+
+    fn duplicate_argument(&self, arg: &str, span: Span) {
+        self.emitter.emit(Diagnostic::error(
+            Code::DuplicateArgument,
+            Some(span),
+            format!("Duplicate argument '{}'", arg),
+        ));
     }
 }
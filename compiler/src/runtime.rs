@@ -0,0 +1,298 @@
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::diagnostic::{Code, Diagnostic, Emitter};
+
+// The runtime is built into the compiler, so that it needs no setup
+pub const HEADERS: [(&str, &str); 4] = [
+    ("common.h", include_str!("../../runtime/include/common.h")),
+    ("lar.h", include_str!("../../runtime/include/lar.h")),
+    ("memo.h", include_str!("../../runtime/include/memo.h")),
+    ("value.h", include_str!("../../runtime/include/value.h")),
+];
+
+const SOURCES: [(&str, &str); 4] = [
+    ("common.c", include_str!("../../runtime/source/common.c")),
+    ("lar.c", include_str!("../../runtime/source/lar.c")),
+    ("memo.c", include_str!("../../runtime/source/memo.c")),
+    ("value.c", include_str!("../../runtime/source/value.c")),
+];
+
+// The same as a CMake release build, or an instrumented build that keeps the runtime's assertions
+fn cflags(sanitize: Option<&str>) -> Vec<String> {
+    let flags: &[&str] = match sanitize {
+        None => &["-std=c11", "-O3", "-DNDEBUG"],
+        Some(_) => &["-std=c11", "-O1", "-g", "-fno-omit-frame-pointer"],
+    };
+    let mut flags = flags
+        .iter()
+        .map(|flag| flag.to_string())
+        .collect::<Vec<_>>();
+    flags.extend(sanitize.map(|sanitizers| format!("-fsanitize={}", sanitizers)));
+    flags
+}
+
+// The C compiler, which may come with arguments of its own, as in CC="ccache gcc"
+pub fn cc_command(cc: &str) -> Command {
+    let mut words = cc.split_whitespace();
+    let mut command = Command::new(words.next().unwrap_or("gcc"));
+    command.args(words);
+    command
+}
+
+// Where the C compiler finds the runtime's headers and libic.a
+pub struct Runtime {
+    pub include: PathBuf,
+    pub lib: PathBuf,
+}
+
+impl Runtime {
+    // The runtime of an ic repository, when IC_HOME overrides the embedded one. An instrumented
+    // copy is built from its sources into the cache, as the CMake build has none.
+    pub fn from_home(
+        ic_home: &Path,
+        cc: &str,
+        sanitize: Option<&str>,
+        emitter: &Emitter,
+    ) -> Option<Self> {
+        let runtime = ic_home.join("runtime");
+        if !runtime.join("include/value.h").exists() {
+            emitter.emit(Diagnostic::error(
+                Code::RuntimeNotFound,
+                None,
+                format!("The runtime's headers are not in {}", runtime.display()),
+            )
+            .with_help("IC_HOME should be set to the root of the ic repository, or unset to use the runtime built into ic"));
+            return None;
+        }
+        if sanitize.is_some() {
+            let files = read(&runtime.join("include"), &HEADERS)
+                .and_then(|headers| Ok((headers, read(&runtime.join("source"), &SOURCES)?)));
+            return match files {
+                Ok((headers, sources)) => {
+                    Self::build_cached(cc, sanitize, &headers, &sources, emitter)
+                }
+                Err(e) => {
+                    emitter.emit(
+                        Diagnostic::error(
+                            Code::RuntimeNotFound,
+                            None,
+                            format!("The runtime's sources are not in {}", runtime.display()),
+                        )
+                        .with_note(e),
+                    );
+                    None
+                }
+            };
+        }
+        if !runtime.join("lib/libic.a").exists() {
+            emitter.emit(Diagnostic::error(Code::RuntimeNotBuilt, None, "The runtime library is not built")
+                .with_note(format!("{} does not exist", runtime.join("lib/libic.a").display()))
+                .with_help(
+                    "build it with: mkdir -p $IC_HOME/runtime/lib && cd $IC_HOME/runtime/lib && cmake -G\"Unix Makefiles\" -DCMAKE_BUILD_TYPE=Release .. && make",
+                ));
+            return None;
+        }
+        Some(Self {
+            include: runtime.join("include"),
+            lib: runtime.join("lib"),
+        })
+    }
+
+    // The embedded runtime, built on first use into a per-user cache. Each version of the runtime
+    // and of the C compiler, and each set of sanitizers, gets its own build.
+    pub fn cached(cc: &str, sanitize: Option<&str>, emitter: &Emitter) -> Option<Self> {
+        Self::build_cached(cc, sanitize, &HEADERS, &SOURCES, emitter)
+    }
+
+    fn build_cached<S: AsRef<str>>(
+        cc: &str,
+        sanitize: Option<&str>,
+        headers: &[(&str, S)],
+        sources: &[(&str, S)],
+        emitter: &Emitter,
+    ) -> Option<Self> {
+        let flags = cflags(sanitize);
+        let Some(cache) = cache_dir() else {
+            emitter.emit(Diagnostic::error(
+                Code::RuntimeBuildFailed,
+                None,
+                "Can't find a cache directory for the runtime",
+            )
+            .with_help("set HOME or XDG_CACHE_HOME, or set IC_HOME to an ic repository with a built runtime"));
+            return None;
+        };
+
+        let dir = cache.join(format!(
+            "runtime-{}-{:016x}",
+            env!("CARGO_PKG_VERSION"),
+            runtime_key(cc, &flags, headers, sources)
+        ));
+        let runtime = Self {
+            include: dir.join("include"),
+            lib: dir.join("lib"),
+        };
+        if runtime.lib.join("libic.a").exists() {
+            return Some(runtime);
+        }
+
+        // Built aside and moved into place, so that concurrent compilations never see half a runtime.
+        // Builds on other threads of the same process get directories of their own.
+        static BUILDS: AtomicUsize = AtomicUsize::new(0);
+        let tmp = cache.join(format!(
+            "tmp-{}-{}",
+            std::process::id(),
+            BUILDS.fetch_add(1, Ordering::Relaxed)
+        ));
+        // Debug information, which ASan reports and debuggers read, names the sources where they end up
+        // rather than where they are built
+        let mut build_flags = flags.clone();
+        build_flags.push(format!(
+            "-fdebug-prefix-map={}={}",
+            tmp.display(),
+            dir.display()
+        ));
+        let built = build(cc, &build_flags, headers, sources, &tmp).and_then(|()| {
+            match std::fs::rename(&tmp, &dir) {
+                Ok(()) => Ok(()),
+                // Another compilation got there first
+                Err(_) if dir.join("lib/libic.a").exists() => Ok(()),
+                Err(e) => Err(format!(
+                    "can't move the runtime into {}: {}",
+                    dir.display(),
+                    e
+                )),
+            }
+        });
+        let _ = std::fs::remove_dir_all(&tmp);
+        match built {
+            Ok(()) => Some(runtime),
+            Err(e) => {
+                emitter.emit(Diagnostic::error(
+                    Code::RuntimeBuildFailed,
+                    None,
+                    format!("Failed to build the runtime: {}", e),
+                ));
+                None
+            }
+        }
+    }
+}
+
+fn build<S: AsRef<str>>(
+    cc: &str,
+    flags: &[String],
+    headers: &[(&str, S)],
+    sources: &[(&str, S)],
+    dir: &Path,
+) -> Result<(), String> {
+    let include = dir.join("include");
+    let source = dir.join("source");
+    let lib = dir.join("lib");
+    for dir in [&include, &source, &lib] {
+        std::fs::create_dir_all(dir)
+            .map_err(|e| format!("can't create {}: {}", dir.display(), e))?;
+    }
+    for (name, contents) in headers {
+        write(&include.join(name), contents.as_ref())?;
+    }
+
+    // Sources are compiled by their path in dir, which is all that sanitizers record of them
+    let mut objects = Vec::new();
+    for (name, contents) in sources {
+        let path = Path::new("source").join(name);
+        write(&dir.join(&path), contents.as_ref())?;
+        let object = path.with_extension("o");
+        run(cc_command(cc)
+            .current_dir(dir)
+            .args(flags)
+            .arg("-I")
+            .arg("include")
+            .arg("-c")
+            .arg("-o")
+            .arg(&object)
+            .arg(&path))?;
+        objects.push(object);
+    }
+    run(Command::new("ar")
+        .current_dir(dir)
+        .arg("rcs")
+        .arg(Path::new("lib").join("libic.a"))
+        .args(&objects))
+}
+
+// The runtime files of a repository, by the names of the embedded ones
+fn read(dir: &Path, files: &[(&'static str, &str)]) -> Result<Vec<(&'static str, String)>, String> {
+    files
+        .iter()
+        .map(|(name, _)| {
+            let path = dir.join(name);
+            std::fs::read_to_string(&path)
+                .map(|contents| (*name, contents))
+                .map_err(|e| format!("can't read {}: {}", path.display(), e))
+        })
+        .collect()
+}
+
+fn write(path: &Path, contents: &str) -> Result<(), String> {
+    std::fs::write(path, contents).map_err(|e| format!("can't write {}: {}", path.display(), e))
+}
+
+fn run(command: &mut Command) -> Result<(), String> {
+    let tool = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .output()
+        .map_err(|e| format!("can't run {}: {}", tool, e))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} failed ({})\n{}",
+            tool,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        ))
+    }
+}
+
+// Where ic keeps what it builds for later compilations
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache = match std::env::var_os("XDG_CACHE_HOME") {
+        Some(cache) if !cache.is_empty() => PathBuf::from(cache),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".cache"),
+    };
+    Some(cache.join("ic"))
+}
+
+// Identifies a build of the runtime by its sources, the flags and the C compiler's version
+fn runtime_key<S: AsRef<str>>(
+    cc: &str,
+    flags: &[String],
+    headers: &[(&str, S)],
+    sources: &[(&str, S)],
+) -> u64 {
+    let version = cc_command(cc)
+        .arg("--version")
+        .output()
+        .map(|output| output.stdout)
+        .unwrap_or_default();
+    let mut hash = fnv1a(FNV_OFFSET, cc.as_bytes());
+    hash = fnv1a(hash, &version);
+    for (name, contents) in headers.iter().chain(sources) {
+        hash = fnv1a(hash, name.as_bytes());
+        hash = fnv1a(hash, contents.as_ref().as_bytes());
+    }
+    fnv1a(hash, flags.join(" ").as_bytes())
+}
+
+pub const FNV_OFFSET: u64 = 0xcbf29ce484222325;
+
+// 64-bit FNV-1a, which unlike std's hashers is stable across Rust versions
+pub fn fnv1a(mut hash: u64, bytes: &[u8]) -> u64 {
+    for byte in bytes {
+        hash ^= *byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
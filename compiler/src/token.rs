@@ -1,34 +1,43 @@
 use std::fmt::Display;
 
-use crate::loc::Loc;
+use crate::loc::Span;
 
 pub struct Token<'src> {
     pub kind: TokenKind<'src>,
-    pub loc: Loc,
+    pub span: Span,
 }
 
 impl<'src> Token<'src> {
-    pub fn new(kind: TokenKind<'src>, loc: Loc) -> Self {
-        Self { kind, loc }
+    pub fn new(kind: TokenKind<'src>, span: Span) -> Self {
+        Self { kind, span }
     }
 }
 
 #[derive(PartialEq, Clone, Copy)]
 pub enum TokenKind<'src> {
     // Keywords
-    Cons,  // cons
-    Car,   // car
-    Cdr,   // cdr
-    PairQ, // pair?
-    If,    // if
-    Then,  // then
-    Else,  //else
+    Cons,     // cons
+    Car,      // car
+    Cdr,      // cdr
+    PairQ,    // pair?
+    If,       // if
+    Then,     // then
+    Else,     //else
+    Extern,   // extern
+    Lazy,     // lazy
+    Requires, // requires
+    Ensures,  // ensures
 
     // Symbols
     LParen, // (
     RParen, // )
     Comma,  // ,
     Equals, // =
+    At,     // @
+    LBrace, // {
+    RBrace, // }
+    Pipe,   // |
+    Dot,    // .
 
     // Infix Operators
     Add, // +
@@ -45,6 +54,7 @@ pub enum TokenKind<'src> {
     Num(i64),        // [1-9][0-9]*
     Var(&'src str),  // [a-zA-Z][a-zA-Z0-9]*
     Atom(&'src str), // '[a-zA-Z][a-zA-Z0-9]*
+    Str(&'src str),  // "[^"\n]*"
 
     Eof,
 }
@@ -68,13 +78,23 @@ impl<'src> Display for TokenKind<'src> {
             TokenKind::If => write!(f, "'if'"),
             TokenKind::Then => write!(f, "'then'"),
             TokenKind::Else => write!(f, "'else'"),
+            TokenKind::Extern => write!(f, "'extern'"),
+            TokenKind::Lazy => write!(f, "'lazy'"),
+            TokenKind::Requires => write!(f, "'requires'"),
+            TokenKind::Ensures => write!(f, "'ensures'"),
             TokenKind::LParen => write!(f, "'('"),
             TokenKind::RParen => write!(f, "')'"),
             TokenKind::Comma => write!(f, "','"),
             TokenKind::Equals => write!(f, "'='"),
+            TokenKind::At => write!(f, "'@'"),
+            TokenKind::LBrace => write!(f, "'{{'"),
+            TokenKind::RBrace => write!(f, "'}}'"),
+            TokenKind::Pipe => write!(f, "'|'"),
+            TokenKind::Dot => write!(f, "'.'"),
             TokenKind::Num(n) => write!(f, "{}", n),
             TokenKind::Var(v) => write!(f, "{}", v),
             TokenKind::Atom(a) => write!(f, "'{}", a),
+            TokenKind::Str(s) => write!(f, "\"{}\"", s),
             TokenKind::Eof => write!(f, "EOF"),
         }
     }
@@ -0,0 +1,140 @@
+mod common;
+
+use ic::backend::Backend;
+use ic::il;
+use ic::itoc::{self, CBackend};
+use ic::{CompileOptions, Source};
+
+fn options() -> itoc::Options {
+    itoc::Options {
+        interact: false,
+        emit_lib: false,
+        contracts: true,
+    }
+}
+
+fn to_il(source: Source) -> il::Program {
+    let program = ic::parse(source).unwrap();
+    ic::to_il(ic::to_hir(source, program, &CompileOptions::default()).unwrap())
+}
+
+fn generate(path: &str, text: &str) -> String {
+    let source = Source::new(path, text);
+    let mut out = Vec::new();
+    CBackend::new(options(), source)
+        .generate(to_il(source), &mut out)
+        .unwrap();
+    String::from_utf8(out).unwrap()
+}
+
+fn generate_units(path: &str, text: &str) -> Vec<String> {
+    let source = Source::new(path, text);
+    CBackend::new(options(), source).generate_units(to_il(source))
+}
+
+// The unit that defines each of the first definitions of `common::definitions`
+fn units_of(units: &[String], count: usize) -> Vec<usize> {
+    (0..count)
+        .map(|i| {
+            let definition = format!("IC_VALUE __fl_{}(IC_LAR_PROTO* lar)\n", common::name(i));
+            units
+                .iter()
+                .position(|unit| unit.contains(&definition))
+                .unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn generates_c() {
+    let source = Source::new("sq.fl", "result = sq(3)\nsq(n) = n * n\n");
+    assert_eq!(CBackend::new(options(), source).extension(), "c");
+
+    let c = generate("sq.fl", "result = sq(3)\nsq(n) = n * n\n");
+    assert!(c.contains("#include"));
+    assert!(c.contains("static IC_VALUE sq(IC_LAR_PROTO* lar)"));
+    assert!(c.contains("IC_mul_at("));
+}
+
+#[test]
+fn a_small_program_is_a_single_unit() {
+    let text = "result = sq(3)\nsq(n) = n * n\n";
+    assert_eq!(generate_units("sq.fl", text), [generate("sq.fl", text)]);
+}
+
+#[test]
+fn checks_contracts_when_asked() {
+    let text = "result = sq(3)\nsq(n) requires n > 1 = n * n\n";
+    assert!(generate("sq.fl", text).contains("IC_contract_error"));
+
+    let source = Source::new("sq.fl", text);
+    let options = itoc::Options {
+        contracts: false,
+        ..options()
+    };
+    let mut out = Vec::new();
+    CBackend::new(options, source)
+        .generate(to_il(source), &mut out)
+        .unwrap();
+    assert!(!String::from_utf8(out)
+        .unwrap()
+        .contains("IC_contract_error"));
+}
+
+#[test]
+fn resolves_fields_of_a_single_shape_statically() {
+    let c = generate("point.fl", "result = p.x\np = {x = 1, y = 2}\n");
+    assert!(c.contains("IC_field_slot("));
+    assert!(!c.contains("__slots_x"));
+}
+
+#[test]
+fn looks_up_fields_of_several_shapes_in_a_table() {
+    let text = "\
+result = cons(getx(a), getx(b))
+a = {x = 1}
+b = {y = 2, x = 3}
+getx(r) = r.x
+";
+    let c = generate("points.fl", text);
+    assert!(c.contains("__slots_x"));
+    assert!(c.contains("IC_field_at("));
+}
+
+#[test]
+fn splits_a_large_program_into_units() {
+    let units = generate_units("chain.fl", &common::chain(600, "1"));
+    assert!(units.len() > 2);
+    assert!(units[0].contains("int main(int argc, char** argv)"));
+    // Definitions have external linkage across units, under a prefix
+    assert!(units[1..]
+        .iter()
+        .any(|unit| unit.contains("IC_VALUE __fl_xaa(")));
+}
+
+#[test]
+fn a_change_regenerates_only_its_unit() {
+    let before = generate_units("chain.fl", &common::chain(600, "1"));
+    let after = generate_units("chain.fl", &common::chain(600, "2"));
+    assert_eq!(before.len(), after.len());
+    let changed = before.iter().zip(&after).filter(|(a, b)| a != b).count();
+    assert_eq!(changed, 1);
+}
+
+#[test]
+fn a_growing_program_moves_definitions_out_of_a_single_unit() {
+    let before = generate_units("many.fl", &common::definitions(300));
+    let after = generate_units("many.fl", &common::definitions(600));
+    assert_eq!(after.len(), before.len() + 1);
+
+    let new = before.len();
+    let moved = units_of(&before, 300)
+        .into_iter()
+        .zip(units_of(&after, 300))
+        .filter(|(before, after)| before != after)
+        .collect::<Vec<_>>();
+    assert!(!moved.is_empty());
+    assert!(moved
+        .iter()
+        .all(|&(from, to)| from == moved[0].0 && to == new));
+}
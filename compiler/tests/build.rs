@@ -0,0 +1,78 @@
+mod common;
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ic::build::Builder;
+use ic::diagnostic::Emitter;
+use ic::{BuildOptions, CompileOptions, Source};
+
+fn objects(dir: &Path) -> Vec<PathBuf> {
+    let mut objects = std::fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "o"))
+        .collect::<Vec<_>>();
+    objects.sort();
+    objects
+}
+
+fn modified(path: &Path) -> SystemTime {
+    std::fs::metadata(path).unwrap().modified().unwrap()
+}
+
+// Builds the program and returns its result, which it prints before its timings
+fn build(builder: &Builder, text: &str) -> String {
+    let options = CompileOptions {
+        split: true,
+        ..CompileOptions::default()
+    };
+    let artifact = ic::compile(Source::new("chain.fl", text), &options).unwrap();
+    let runtime = builder.runtime().unwrap();
+    assert!(builder.build(&artifact.code, &runtime));
+    let output = Command::new(builder.output(false)).output().unwrap();
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    stdout.lines().next().unwrap().to_string()
+}
+
+#[test]
+fn compiles_again_only_the_units_that_changed() {
+    let build_dir = common::build_dir();
+    let options = BuildOptions {
+        build_dir: build_dir.clone(),
+        ..BuildOptions::default()
+    };
+    let text = common::chain(600, "1");
+    let emitter = Emitter::collecting("chain.fl", &text);
+    let builder = Builder::new(&options, &emitter);
+
+    assert_eq!(build(&builder, &text), "600");
+    let objects = objects(&build_dir);
+    assert!(objects.len() > 2);
+
+    // Marks the objects, to tell which ones are compiled again
+    for object in &objects {
+        File::options()
+            .write(true)
+            .open(object)
+            .unwrap()
+            .set_modified(UNIX_EPOCH)
+            .unwrap();
+    }
+    let recompiled = || {
+        objects
+            .iter()
+            .filter(|object| modified(object) != UNIX_EPOCH)
+            .count()
+    };
+
+    assert_eq!(build(&builder, &text), "600");
+    assert_eq!(recompiled(), 0);
+
+    assert_eq!(build(&builder, &common::chain(600, "2")), "601");
+    assert_eq!(recompiled(), 1);
+
+    std::fs::remove_dir_all(&build_dir).unwrap();
+}
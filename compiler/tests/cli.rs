@@ -0,0 +1,122 @@
+use std::path::PathBuf;
+
+use ic::cli::{self, Args, Command, Emit};
+use ic::diagnostic::ErrorFormat;
+use ic::lint::Lint;
+
+fn parse(args: &[&str]) -> Result<Command, String> {
+    cli::parse(args.iter().map(|arg| arg.to_string()))
+}
+
+fn compile(args: &[&str]) -> Args {
+    match parse(args) {
+        Ok(Command::Compile(args)) => args,
+        other => panic!("expected a compilation, got {:?}", other),
+    }
+}
+
+#[test]
+fn parses_a_compilation() {
+    let args = compile(&[
+        "prog.fl",
+        "-o",
+        "prog",
+        "--build-dir=build",
+        "--emit",
+        "c",
+        "--no-contracts",
+        "hash.c",
+        "-lm",
+    ]);
+    assert_eq!(args.path, "prog.fl");
+    assert_eq!(args.output, Some(PathBuf::from("prog")));
+    assert_eq!(args.build_dir, Some(PathBuf::from("build")));
+    assert_eq!(args.emit, Emit::C);
+    assert!(!args.contracts);
+    assert_eq!(args.link, ["hash.c", "-lm"]);
+}
+
+#[test]
+fn defaults_to_building_an_executable() {
+    let args = compile(&["prog.fl"]);
+    assert_eq!(args.emit, Emit::Exe);
+    assert_eq!(args.output, None);
+    assert!(args.contracts);
+    assert!(!args.debug);
+    assert_eq!(args.error_format, ErrorFormat::Human);
+    assert_eq!(args.lints, Lint::ALL);
+}
+
+#[test]
+fn adds_up_flags() {
+    let args = compile(&["prog.fl", "--cflags=-O1 -Wall", "--cflags", "-g"]);
+    assert_eq!(args.cflags, ["-O1", "-Wall", "-g"]);
+}
+
+#[test]
+fn enables_and_silences_lints() {
+    let args = compile(&["prog.fl", "-A", "all", "-Wunused-argument"]);
+    assert_eq!(args.lints, [Lint::UnusedArgument]);
+
+    let args = compile(&["prog.fl", "-Aunreachable-definition"]);
+    assert!(!args.lints.contains(&Lint::UnreachableDefinition));
+    assert_eq!(args.lints.len(), Lint::ALL.len() - 1);
+}
+
+#[test]
+fn forwards_the_arguments_of_a_run() {
+    match parse(&["run", "--debug", "prog.fl", "--", "-o", "x"]) {
+        Ok(Command::Run(args, program_args)) => {
+            assert_eq!(args.path, "prog.fl");
+            assert!(args.debug);
+            assert_eq!(program_args, ["-o", "x"]);
+        }
+        other => panic!("expected a run, got {:?}", other),
+    }
+}
+
+#[test]
+fn builds_the_project_of_the_current_directory() {
+    match parse(&["build", "--profile", "release"]) {
+        Ok(Command::Build(args)) => {
+            assert_eq!(args.path, ".");
+            assert_eq!(args.profile.as_deref(), Some("release"));
+        }
+        other => panic!("expected a build, got {:?}", other),
+    }
+}
+
+#[test]
+fn prints_help_and_version() {
+    assert!(matches!(parse(&["prog.fl", "--help"]), Ok(Command::Help)));
+    assert!(matches!(parse(&["-V"]), Ok(Command::Version)));
+}
+
+#[test]
+fn rejects_invalid_command_lines() {
+    let error = |args: &[&str]| parse(args).unwrap_err();
+    assert_eq!(error(&[]), "Source file required");
+    assert_eq!(
+        error(&["prog.fl", "--frobnicate"]),
+        "Unknown option: --frobnicate"
+    );
+    assert_eq!(error(&["a.fl", "b.fl"]), "Unexpected argument: b.fl");
+    assert_eq!(error(&["prog.fl", "-o"]), "-o requires a value");
+    assert_eq!(
+        error(&["prog.fl", "--emit=obj"]),
+        "Unknown stage to emit: obj"
+    );
+    assert_eq!(error(&["prog.fl", "-Wfoo"]), "Unknown lint: foo");
+    assert_eq!(
+        error(&["prog.fl", "--profile", "release"]),
+        "--profile can only be used with 'ic build'"
+    );
+    assert_eq!(
+        error(&["run", "prog.fl", "--emit=c"]),
+        "'ic run' can't be used with --emit"
+    );
+    assert_eq!(
+        error(&["prog.fl", "--interact", "--emit-lib"]),
+        "--interact can't be used with --emit-lib"
+    );
+}
@@ -0,0 +1,124 @@
+// Fixtures shared by the test files, each of which uses only some of them
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use ic::{BuildOptions, CompileOptions, Source};
+
+// The name of the i-th generated definition, as fl names are letters only
+pub fn name(i: usize) -> String {
+    format!(
+        "x{}{}",
+        (b'a' + (i / 26) as u8) as char,
+        (b'a' + (i % 26) as u8) as char
+    )
+}
+
+// A program long enough to be split, of a chain of definitions each adding one to the next, with
+// the last one given
+pub fn chain(length: usize, last: &str) -> String {
+    let mut text = format!("result = {}\n", name(0));
+    for i in 0..length - 1 {
+        text += &format!("{} = {} + 1\n", name(i), name(i + 1));
+    }
+    text += &format!("{} = {}\n", name(length - 1), last);
+    text
+}
+
+// A program of independent definitions, each of its own number
+pub fn definitions(count: usize) -> String {
+    let mut text = format!("result = {}\n", name(0));
+    for i in 0..count {
+        text += &format!("{} = {}\n", name(i), i + 1);
+    }
+    text
+}
+
+// A build directory of its own, for tests that run in parallel
+pub fn build_dir() -> PathBuf {
+    static DIRS: AtomicUsize = AtomicUsize::new(0);
+    std::env::temp_dir().join(format!(
+        "ic-test-{}-{}",
+        std::process::id(),
+        DIRS.fetch_add(1, Ordering::Relaxed)
+    ))
+}
+
+// The examples of the repository
+pub fn example(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("../examples")
+        .join(name)
+}
+
+// What a program wrote, and whether it succeeded
+pub struct Output {
+    pub stdout: String,
+    pub stderr: String,
+    pub success: bool,
+}
+
+impl Output {
+    // The result, which a program prints before its timings
+    pub fn result(&self) -> &str {
+        self.stdout.lines().next().unwrap_or("")
+    }
+}
+
+// Builds a program instrumented with AddressSanitizer, which catches the runtime using memory that
+// the collector freed, and runs it with the given arguments and stdin
+pub fn run(
+    path: &str,
+    text: &str,
+    options: &CompileOptions,
+    link: &[&Path],
+    args: &[&str],
+    stdin: &str,
+) -> Output {
+    let source = Source::new(path, text);
+    let artifact = ic::compile(source, options).unwrap_or_else(|e| panic!("{}", e));
+    let build_dir = build_dir();
+    let build_options = BuildOptions {
+        build_dir: build_dir.clone(),
+        link: link.iter().map(|path| path.display().to_string()).collect(),
+        sanitize: Some("address".to_string()),
+        ..BuildOptions::default()
+    };
+    let built =
+        ic::build(source, &artifact.code, &build_options).unwrap_or_else(|e| panic!("{}", e));
+
+    let mut child = Command::new(&built.output)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(stdin.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    std::fs::remove_dir_all(&build_dir).unwrap();
+    Output {
+        stdout: String::from_utf8(output.stdout).unwrap(),
+        stderr: String::from_utf8(output.stderr).unwrap(),
+        success: output.status.success(),
+    }
+}
+
+// Runs a program with the default options, and without input
+pub fn run_program(path: &str, text: &str) -> Output {
+    run(path, text, &CompileOptions::default(), &[], &[], "")
+}
+
+// Runs an example of examples/fl with stdin
+pub fn run_example(name: &str, options: &CompileOptions, link: &[&Path], stdin: &str) -> Output {
+    let text = std::fs::read_to_string(example(&format!("fl/{}", name))).unwrap();
+    run(name, &text, options, link, &[], stdin)
+}
@@ -0,0 +1,183 @@
+mod common;
+
+use ic::diagnostic::{Code, Diagnostic, Diagnostics, Severity};
+use ic::{CompileOptions, Source};
+
+const FACT: &str = "\
+result = fact(5)
+
+fact(n) =
+    if n == 1 then
+        1
+    else
+        n * fact(n - 1)
+";
+
+fn errors(path: &str, text: &str) -> Diagnostics {
+    ic::compile(Source::new(path, text), &CompileOptions::default()).unwrap_err()
+}
+
+fn first(diagnostics: &Diagnostics) -> &Diagnostic {
+    diagnostics.iter().next().unwrap()
+}
+
+// The line and column a diagnostic points at
+fn position(diagnostic: &Diagnostic) -> (usize, usize) {
+    let span = diagnostic.span().unwrap();
+    (span.start.line, span.start.col)
+}
+
+#[test]
+fn compiles_a_program_to_a_single_unit() {
+    let artifact = ic::compile(Source::new("fact.fl", FACT), &CompileOptions::default()).unwrap();
+    assert!(artifact.warnings.is_empty());
+    assert!(artifact.code.header.is_none());
+    assert_eq!(artifact.code.units.len(), 1);
+
+    let c = &artifact.code.units[0];
+    assert!(c.contains("int main(int argc, char** argv)"));
+    assert!(c.contains("static IC_VALUE fact(IC_LAR_PROTO* lar)"));
+    // Runtime errors report where they happened in the fl source
+    assert!(c.contains("{\"fact.fl\", 7, 9, \"fact\"}"));
+}
+
+#[test]
+fn compiles_a_library_with_a_header() {
+    let options = CompileOptions {
+        emit_lib: true,
+        ..CompileOptions::default()
+    };
+    let source = Source::new("lib.fl", "sq(n) = n * n\nseven = 7\n");
+    let artifact = ic::compile(source, &options).unwrap();
+
+    let header = artifact.code.header.unwrap();
+    assert!(header.contains("IC_VALUE fl_sq(IC_VALUE /* n */);"));
+    assert!(header.contains("IC_VALUE fl_seven(void);"));
+    assert!(header.contains("void fl_lib_init(void);"));
+    assert!(!artifact.code.units[0].contains("int main("));
+}
+
+#[test]
+fn requires_a_result() {
+    let diagnostics = errors("lib.fl", "sq(n) = n * n\n");
+    assert_eq!(first(&diagnostics).code(), Some(Code::MissingResult));
+}
+
+#[test]
+fn reports_errors_with_their_code_and_location() {
+    let diagnostics = errors("bad.fl", "result = f(x)\nf(a) = a\n");
+    assert_eq!(diagnostics.error_count(), 1);
+    let error = first(&diagnostics);
+    assert_eq!(error.severity(), Severity::Error);
+    assert_eq!(error.code(), Some(Code::UndefinedVariable));
+    assert_eq!(position(error), (1, 12));
+}
+
+#[test]
+fn reports_every_error() {
+    let diagnostics = errors("bad.fl", "result = cons(f(1, 2), g(1))\nf(a) = a\n");
+    let codes = diagnostics
+        .iter()
+        .map(|diagnostic| diagnostic.code())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        codes,
+        [Some(Code::ArityMismatch), Some(Code::UndefinedFunction)]
+    );
+}
+
+#[test]
+fn warns_about_unused_arguments_where_they_are_named() {
+    let source = Source::new("warn.fl", "result = f(1, 2)\nf(a, b) = a\n");
+    let artifact = ic::compile(source, &CompileOptions::default()).unwrap();
+    let warning = first(&artifact.warnings);
+    assert_eq!(warning.severity(), Severity::Warning);
+    assert_eq!(warning.code(), Some(Code::UnusedArgument));
+    assert_eq!(position(warning), (2, 6));
+}
+
+#[test]
+fn silenced_lints_give_no_warnings() {
+    let options = CompileOptions {
+        lints: Vec::new(),
+        ..CompileOptions::default()
+    };
+    let source = Source::new("warn.fl", "result = f(1, 2)\nf(a, b) = a\n");
+    assert!(ic::compile(source, &options).unwrap().warnings.is_empty());
+}
+
+#[test]
+fn definitions_hide_builtins() {
+    let source = Source::new(
+        "hide.fl",
+        "result = length(cons(1, 'nil))\nlength(xs) = if pair?(xs) then 1 + length(cdr(xs)) else 1 - 1\n",
+    );
+    let artifact = ic::compile(source, &CompileOptions::default()).unwrap();
+    assert!(!artifact.code.units[0].contains("IC_length_at("));
+
+    let source = Source::new(
+        "builtin.fl",
+        "result = length(array(3, sq))\nsq(n) = n * n\n",
+    );
+    let artifact = ic::compile(source, &CompileOptions::default()).unwrap();
+    assert!(artifact.code.units[0].contains("IC_length_at("));
+
+    let source = Source::new(
+        "read.fl",
+        "result = sq(readValue)\nsq(readValue) = readValue * readValue\n",
+    );
+    let c = &ic::compile(source, &CompileOptions::default())
+        .unwrap()
+        .code
+        .units[0];
+    assert_eq!(c.matches("IC_value_read()").count(), 1);
+}
+
+#[test]
+fn reports_fields_a_known_record_lacks() {
+    let text = "\
+result = cons(p.z, cons({p | y = 3}.x, {a = 1}.b))
+p = {x = 1, y = 2}
+getx(r) = r.x
+";
+    let diagnostics = errors("fields.fl", text);
+    let found = diagnostics
+        .iter()
+        .map(|diagnostic| (diagnostic.code(), position(diagnostic)))
+        .collect::<Vec<_>>();
+    assert_eq!(
+        found,
+        [
+            (Some(Code::UnknownField), (1, 15)),
+            (Some(Code::UnknownField), (1, 40)),
+        ]
+    );
+    assert_eq!(first(&diagnostics).message(), "Record has no field z");
+
+    // Fields of records that aren't known at compile time are looked up at runtime
+    let text = "result = getx({y = 1})\ngetx(r) = r.x\np = {x = 1}\n";
+    assert!(ic::compile(Source::new("fields.fl", text), &CompileOptions::default()).is_ok());
+}
+
+#[test]
+fn rejects_too_many_arguments() {
+    let args = (0..255).map(common::name).collect::<Vec<_>>();
+    let text = format!(
+        "result = 1\nf({}) ensures result > 1 = 2\n",
+        args.join(", ")
+    );
+    let diagnostics = errors("many.fl", &text);
+    assert_eq!(first(&diagnostics).code(), Some(Code::TooManyArguments));
+}
+
+#[test]
+fn emits_every_stage() {
+    let source = Source::new("fact.fl", FACT);
+    let options = CompileOptions::default();
+    assert!(!ic::tokens(source).unwrap().is_empty());
+    let program = ic::parse(source).unwrap();
+    assert_eq!(program.len(), 2);
+    let hir = ic::to_hir(source, program, &options).unwrap();
+    let il = ic::to_il(hir);
+    assert!(!il.definitions.is_empty());
+}
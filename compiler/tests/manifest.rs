@@ -0,0 +1,119 @@
+use std::path::PathBuf;
+
+use ic::manifest::Manifest;
+
+fn error(text: &str) -> String {
+    Manifest::parse("ic.toml", text).unwrap_err()
+}
+
+#[test]
+fn parses_a_project() {
+    let manifest = Manifest::parse(
+        "ic.toml",
+        r#"
+# The tree sort example
+[package]
+name = "treesort"
+entry = "src/main.fl"
+sources = ["src", "lib"]
+link = [
+    "c/hash.c",   # the hash function
+    "-lm",
+]
+kind = "lib"
+
+[profile.release]
+cflags = ["-march=native"]
+contracts = false
+
+[profile.asan]
+sanitize = "address"
+"#,
+    )
+    .unwrap();
+
+    assert_eq!(manifest.name, "treesort");
+    assert_eq!(manifest.entry, PathBuf::from("src/main.fl"));
+    assert_eq!(
+        manifest.sources,
+        [PathBuf::from("src"), PathBuf::from("lib")]
+    );
+    assert_eq!(manifest.link, ["c/hash.c", "-lm"]);
+    assert!(manifest.lib);
+
+    let release = manifest.profile("release").unwrap();
+    assert_eq!(release.cflags, ["-march=native"]);
+    assert!(!release.contracts);
+    assert!(!release.debug);
+    assert!(manifest.profile("dev").unwrap().debug);
+    assert!(manifest.profile("profile").unwrap().debug_info);
+    assert_eq!(
+        manifest.profile("asan").unwrap().sanitize.as_deref(),
+        Some("address")
+    );
+    assert!(manifest.profile("fast").is_none());
+}
+
+#[test]
+fn requires_a_name_and_an_entry() {
+    assert_eq!(
+        error("[package]\nentry = \"main.fl\"\n"),
+        "ic.toml: [package] has no name"
+    );
+    assert_eq!(
+        error("[package]\nname = \"p\"\n"),
+        "ic.toml: [package] has no entry"
+    );
+}
+
+#[test]
+fn reports_errors_with_their_line() {
+    assert_eq!(
+        error("[package]\nname = \"p\"\n[dependencies]\n"),
+        "ic.toml:3: unknown table: [dependencies]"
+    );
+    assert_eq!(
+        error("[package]\nversion = \"1.0\"\n"),
+        "ic.toml:2: unknown key 'version' in [package]"
+    );
+    assert_eq!(
+        error("name = \"p\"\n"),
+        "ic.toml:1: 'name' must be in a table, such as [package]"
+    );
+    assert_eq!(
+        error("[package\n"),
+        "ic.toml:1: unterminated table header: [package"
+    );
+    assert_eq!(
+        error("[package]\nname\n"),
+        "ic.toml:2: expected 'key = value': name"
+    );
+}
+
+#[test]
+fn checks_the_types_of_values() {
+    assert_eq!(
+        error("[package]\nname = [\"p\"]\n"),
+        "ic.toml:2: 'name' must be a string"
+    );
+    assert_eq!(
+        error("[package]\nkind = \"dylib\"\n"),
+        "ic.toml:2: 'kind' must be \"bin\" or \"lib\""
+    );
+    assert_eq!(
+        error("[package]\nsources = \"src\"\n"),
+        "ic.toml:2: 'sources' must be an array of strings"
+    );
+    assert_eq!(
+        error("[profile.dev]\ndebug = \"yes\"\n"),
+        "ic.toml:2: 'debug' must be true or false"
+    );
+}
+
+#[test]
+fn reports_unterminated_arrays() {
+    assert_eq!(
+        error("[package]\nlink = [\"-lm\",\n"),
+        "ic.toml:2: unterminated array"
+    );
+}
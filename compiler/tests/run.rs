@@ -0,0 +1,236 @@
+// Programs built and run under AddressSanitizer, which also catches the collector freeing values
+// that are still in use
+mod common;
+
+use common::{run, run_example, run_program};
+use ic::CompileOptions;
+
+const FIB: &str = "
+fib(n) =
+    if n < 2 then
+        n
+    else
+        fib(n - 1) + fib(n - 2)
+";
+
+#[test]
+fn runs_the_examples() {
+    let examples = [
+        ("fact.fl", "1307674368000"),
+        ("fibArray.fl", "2880067194370816120"),
+        ("fibMemo.fl", "2880067194370816120"),
+        ("prog.fl", "(1 . (2 . (3 . (4 . 'nil))))"),
+        ("records.fl", "{age = 44, name = 'bob}"),
+        (
+            "treeSort.fl",
+            "(1 . (2 . (3 . (3 . (4 . (5 . (6 . (9 . (10 . (13 . (15 . 'nil)))))))))))",
+        ),
+    ];
+    for (name, result) in examples {
+        let output = run_example(name, &CompileOptions::default(), &[], "");
+        assert!(output.success, "{}: {}", name, output.stderr);
+        assert_eq!(output.result(), result, "{}", name);
+    }
+}
+
+#[test]
+fn compares_values_that_are_collected_meanwhile() {
+    let output = run_example("compareCollected.fl", &CompileOptions::default(), &[], "");
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.result(), "('true . 'false)");
+}
+
+#[test]
+fn compares_long_lists() {
+    let output = run_example("compareLong.fl", &CompileOptions::default(), &[], "");
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(
+        output.result(),
+        "('true . ('true . ('true . ('true . 'nil))))"
+    );
+}
+
+#[test]
+fn compares_and_orders_values() {
+    let text = "
+result = cons(cons(1, 2) < cons(1, 3),
+    cons({x = 1, y = 'a} == {y = 'a, x = 1},
+        cons(array(3, sq) == array(3, sq),
+            cons(array(2, sq) < array(3, sq),
+                cons(2 >= 3, 'nil)))))
+
+sq(i) = i * i
+";
+    let output = run_program("order.fl", text);
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(
+        output.result(),
+        "('true . ('true . ('true . ('true . ('false . 'nil)))))"
+    );
+}
+
+#[test]
+fn calls_c_functions() {
+    let hash = common::example("c/hash.c");
+    let output = run_example("extern.fl", &CompileOptions::default(), &[&hash], "");
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.result(), "(967507 . (7 . (3 . 'nil)))");
+}
+
+#[test]
+fn streams_the_result_of_an_interactive_program() {
+    let options = CompileOptions {
+        interact: true,
+        ..CompileOptions::default()
+    };
+    let output = run_example("upcase.fl", &options, &[], "hello\n");
+    assert!(output.success, "{}", output.stderr);
+    assert!(output.stdout.starts_with("HELLO\n"), "{}", output.stdout);
+}
+
+#[test]
+fn keeps_input_while_it_is_compared() {
+    let text = format!(
+        "
+result = if first(input) == cons(104, 'nil) then cons(fib(25), len(input)) else 'nil
+
+first(xs) = cons(car(xs), 'nil)
+
+len(xs) = if pair?(xs) then 1 + len(cdr(xs)) else 1 - 1
+{}",
+        FIB
+    );
+    let output = run(
+        "input.fl",
+        &text,
+        &CompileOptions::default(),
+        &[],
+        &[],
+        "hello world",
+    );
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.result(), "(75025 . 11)");
+}
+
+#[test]
+fn reads_a_value() {
+    let output = run_example(
+        "treeSortInput.fl",
+        &CompileOptions::default(),
+        &[],
+        "(3 . (1 . (2 . 'nil)))",
+    );
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.result(), "(1 . (2 . (3 . 'nil)))");
+
+    let path = common::build_dir().with_extension("txt");
+    std::fs::write(&path, "[1 (2 . 'x) 'y]").unwrap();
+    let text = "result = cons(length(readValue), at(readValue, 1))\n";
+    let args = ["--input", path.to_str().unwrap()];
+    let output = run("read.fl", text, &CompileOptions::default(), &[], &args, "");
+    std::fs::remove_file(&path).unwrap();
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.result(), "(3 . (2 . 'x))");
+}
+
+#[test]
+fn keeps_the_value_read_while_it_is_compared() {
+    let text = format!(
+        "
+result = if first(readValue) == cons(1, 'nil) then cons(fib(25), len(readValue)) else 'nil
+
+first(xs) = cons(car(xs), 'nil)
+
+len(xs) = if pair?(xs) then 1 + len(cdr(xs)) else 1 - 1
+{}",
+        FIB
+    );
+    let stdin = "(1 . (2 . (3 . 'nil)))";
+    let output = run(
+        "read.fl",
+        &text,
+        &CompileOptions::default(),
+        &[],
+        &[],
+        stdin,
+    );
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.result(), "(75025 . 3)");
+}
+
+#[test]
+fn reports_where_reading_a_value_fails() {
+    let text = "result = car(readValue)\n";
+    let output = run("read.fl", text, &CompileOptions::default(), &[], &[], "(1");
+    assert!(!output.success);
+    assert!(
+        output.stderr.contains("<stdin>:1:3: expected '.'"),
+        "{}",
+        output.stderr
+    );
+}
+
+#[test]
+fn checks_contracts() {
+    let text = "
+result = fact(1 - 1)
+
+fact(n) requires n >= 1 ensures result >= n =
+    if n == 1 then 1 else n * fact(n - 1)
+";
+    let output = run_program("contract.fl", text);
+    assert!(!output.success);
+    assert!(
+        output
+            .stderr
+            .starts_with("[Runtime error]: precondition of fact violated with n = 0\n"),
+        "{}",
+        output.stderr
+    );
+
+    // Arguments that haven't been evaluated aren't forced to show them
+    let text = "result = same(cons(7, 'nil))\n\nsame(xs) ensures pair?(result) == 'false = xs\n";
+    let output = run_program("post.fl", text);
+    assert!(!output.success);
+    assert!(
+        output.stderr.starts_with(
+            "[Runtime error]: postcondition of same violated with xs = (_ . _), result = (_ . _)\n"
+        ),
+        "{}",
+        output.stderr
+    );
+
+    let options = CompileOptions {
+        contracts: false,
+        ..CompileOptions::default()
+    };
+    let output = run("post.fl", text, &options, &[], &[], "");
+    assert!(output.success, "{}", output.stderr);
+    assert_eq!(output.result(), "(7 . 'nil)");
+}
+
+#[test]
+fn reports_where_runtime_errors_happen() {
+    let text = "
+result = sum(array(3, sq), 3)
+
+sq(i) = i * i
+
+sum(a, i) =
+    if i < 1 then
+        1 - 1
+    else
+        at(a, i) + sum(a, i - 1)
+";
+    let output = run_program("bounds.fl", text);
+    assert!(!output.success);
+    assert_eq!(
+        output.stderr,
+        "\
+[Runtime error]: bounds.fl:10:9 in sum: index 3 is out of bounds for an array of length 3
+Demanded by, most recent first:
+\tsum at bounds.fl:6:1
+\tresult at bounds.fl:2:1
+"
+    );
+}
@@ -0,0 +1,18 @@
+#include "value.h"
+
+IC_VALUE c_hash(IC_VALUE x)
+{
+	if (x.tag != IC_VALUE_INTEGER) {
+		IC_runtime_error("hash expects an integer");
+	}
+	unsigned long h = (unsigned long)x.as.integer * 2654435761UL;
+	return IC_INTEGER((long)(h % 1000003));
+}
+
+IC_VALUE c_orElse(IC_VALUE x, IC_THUNK fallback)
+{
+	if (x.tag == IC_VALUE_ATOM && x.as.atom == 0) {
+		return IC_THUNK_FORCE(fallback);
+	}
+	return x;
+}
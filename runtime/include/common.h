@@ -9,6 +9,30 @@ typedef uintptr_t usize;
 
 typedef enum { false, true } bool;
 
+/* Where an fl operation is in the source, and the definition it is in */
+typedef struct {
+	const char* file;
+	u32 line;
+	u32 col;
+	const char* function;
+} IC_LOC;
+
+/* A definition or thunk being evaluated, named after the fl code it comes from */
+typedef struct {
+	const char* name;
+	IC_LOC loc;
+} IC_DEMAND;
+
+/* The shadow stack of demands is a ring, so that deep recursion only loses the oldest ones */
+#define IC_DEMANDS_MAX 1024
+
+extern const IC_DEMAND* IC_demands[IC_DEMANDS_MAX];
+extern usize IC_demand_depth;
+
+#define IC_DEMAND_PUSH(_demand) IC_demands[IC_demand_depth++ % IC_DEMANDS_MAX] = (_demand)
+#define IC_DEMAND_POP() IC_demand_depth--
+
 __attribute__((noreturn)) void IC_runtime_error(const char* fmt, ...);
+__attribute__((noreturn)) void IC_runtime_error_at(const IC_LOC* loc, const char* fmt, ...);
 
 #endif /* IC_COMMON_H */
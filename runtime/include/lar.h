@@ -3,17 +3,48 @@
 
 #include "common.h"
 
+enum { IC_LAR_FRAME, IC_LAR_ARRAY, IC_LAR_RECORD };
+
 typedef struct IC_lar_proto {
 	struct IC_lar_proto* parent;
 	struct IC_lar_proto* gc_next;
 	u8 num_of_args;
 	u8 in_stack;
 	u8 marked;
+	u8 kind;
 } IC_LAR_PROTO;
 
+/* An array is laid out like a LAR with length arguments, whose thunks all give an element from its index */
+typedef struct {
+	IC_LAR_PROTO proto;
+	usize length;
+} IC_ARRAY_PROTO;
+
+/* The field names of a record, in the order of its slots. Each set of field names in a program's record literals is
+ * a shape with its own id, which indexes the tables that map field names to slots. */
+typedef struct {
+	u32 id;
+	u8 num_of_fields;
+	const char** fields;
+} IC_SHAPE;
+
+/* A record's slots are single argument LARs, so that a functional update can share the fields it doesn't change */
+typedef struct {
+	IC_LAR_PROTO proto;
+	const IC_SHAPE* shape;
+} IC_RECORD_PROTO;
+
 typedef struct IC_value IC_VALUE;
 typedef IC_VALUE (*IC_LARF)(IC_LAR_PROTO*);
 
+/* An unevaluated argument, handed to extern functions that take it lazily */
+typedef struct {
+	IC_LAR_PROTO* lar;
+	u32 arg;
+} IC_THUNK;
+
+#define IC_THUNK_FORCE(_thunk) IC_lar_get_arg((_thunk).lar, (_thunk).arg)
+
 #define IC_FUNCTION_PUSH(_lar) (_lar)->in_stack = 1
 #define IC_FUNCTION_POP(_lar) (_lar)->in_stack = 0
 
@@ -22,10 +53,45 @@ typedef IC_VALUE (*IC_LARF)(IC_LAR_PROTO*);
 #define IC_LAR_VALUE(_lar, _i)                                                                                         \
 	*(IC_VALUE*)((u8*)(_lar) + sizeof(IC_LAR_PROTO) + _lar->num_of_args * sizeof(IC_LARF) + _i * sizeof(IC_VALUE))
 
+#define IC_ARRAY_LENGTH(_array) ((IC_ARRAY_PROTO*)(_array))->length
+
+#define IC_ARRAY_THUNK(_array, _i) *(IC_LARF*)((u8*)(_array) + sizeof(IC_ARRAY_PROTO) + (_i) * sizeof(IC_LARF))
+
+#define IC_ARRAY_VALUE(_array, _i)                                                                                     \
+	*(IC_VALUE*)((u8*)(_array) + sizeof(IC_ARRAY_PROTO) + IC_ARRAY_LENGTH(_array) * sizeof(IC_LARF) +                  \
+	             (_i) * sizeof(IC_VALUE))
+
+#define IC_RECORD_SHAPE(_record) ((IC_RECORD_PROTO*)(_record))->shape
+
+#define IC_RECORD_FIELD(_record, _i)                                                                                   \
+	*(IC_LAR_PROTO**)((u8*)(_record) + sizeof(IC_RECORD_PROTO) + (_i) * sizeof(IC_LAR_PROTO*))
+
 IC_LAR_PROTO* IC_lar_new(IC_LAR_PROTO* parent, u8 num_of_args, IC_LARF* args);
 
+IC_LAR_PROTO* IC_array_new(usize length, IC_LARF function);
+
+IC_VALUE IC_array_get(IC_LAR_PROTO* array, usize index);
+
+IC_LAR_PROTO* IC_record_new(const IC_SHAPE* shape, IC_LAR_PROTO* parent, IC_LARF* fields);
+
+IC_LAR_PROTO* IC_record_with(IC_LAR_PROTO* record, u8 slot, IC_LAR_PROTO* parent, IC_LARF field);
+
 IC_VALUE IC_lar_get_arg(IC_LAR_PROTO* lar, u32 arg);
 
+IC_LAR_PROTO* IC_lar_extend(IC_LAR_PROTO* lar, IC_VALUE value);
+
+/* The list of stdin's characters, read as it is forced. Its head stays alive for the whole run, so
+ * everything read is retained. */
+IC_VALUE IC_input(void);
+
+void IC_root_push(IC_VALUE value);
+void IC_root_pop(usize count);
+
+/* Rooted values by index, counted from the bottom, so that a slot can follow a value being traversed */
+usize IC_root_count(void);
+IC_VALUE IC_root_get(usize index);
+void IC_root_set(usize index, IC_VALUE value);
+
 double IC_get_gc_time(void);
 
 usize IC_get_alloc_size(void);
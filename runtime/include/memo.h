@@ -0,0 +1,25 @@
+#ifndef IC_MEMO_H
+#define IC_MEMO_H
+
+#include "value.h"
+
+typedef struct IC_memo_entry IC_MEMO_ENTRY;
+
+/* The results of a @memo function, keyed on its fully evaluated arguments */
+typedef struct IC_memo {
+	IC_MEMO_ENTRY** buckets;
+	usize num_of_buckets;
+	usize num_of_entries;
+	u8 num_of_args;
+	struct IC_memo* next;
+} IC_MEMO;
+
+#define IC_MEMO_INIT(_num_of_args) {NULL, 0, 0, (_num_of_args), NULL}
+
+bool IC_memo_lookup(IC_MEMO* memo, IC_VALUE* key, IC_VALUE* result);
+void IC_memo_insert(IC_MEMO* memo, IC_VALUE* key, IC_VALUE result);
+
+void IC_memo_mark(void (*mark)(IC_VALUE));
+void IC_memo_cleanup(void);
+
+#endif /* IC_MEMO_H */
@@ -3,36 +3,78 @@
 
 #include "lar.h"
 
-extern const char* IC_atom_names[];
+extern const char** IC_atom_names;
 
 struct IC_value {
-	enum { IC_VALUE_INTEGER, IC_VALUE_ATOM, IC_VALUE_PAIR } tag;
+	enum { IC_VALUE_INTEGER, IC_VALUE_ATOM, IC_VALUE_PAIR, IC_VALUE_ARRAY, IC_VALUE_RECORD } tag;
 	union {
 		long integer;
 		usize atom;
 		IC_LAR_PROTO* pair;
+		IC_LAR_PROTO* array;
+		IC_LAR_PROTO* record;
 	} as;
 };
 
 #define IC_INTEGER(x) ((IC_VALUE){IC_VALUE_INTEGER, {.integer = (x)}})
 #define IC_ATOM(x) ((IC_VALUE){IC_VALUE_ATOM, {.atom = (x)}})
 #define IC_PAIR(l) ((IC_VALUE){IC_VALUE_PAIR, {.pair = (l)}})
+#define IC_ARRAY(l) ((IC_VALUE){IC_VALUE_ARRAY, {.array = (l)}})
+#define IC_RECORD(l) ((IC_VALUE){IC_VALUE_RECORD, {.record = (l)}})
 
 #define IC_IS_PAIR(v) ((v).tag == IC_VALUE_PAIR ? IC_ATOM(1) : IC_ATOM(2))
 
 #define IC_IS_TRUTHY(v) ((v).tag == IC_VALUE_ATOM && (v).as.atom == 1)
 
+void IC_atoms_init(const char** names, usize count);
+usize IC_atom_intern(const char* name, usize len);
+IC_VALUE IC_atom_named(const char* name);
+const char* IC_atom_name(IC_VALUE atom);
+
+IC_VALUE IC_cons(IC_VALUE car, IC_VALUE cdr);
 IC_VALUE IC_add(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_sub(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_mul(IC_VALUE a, IC_VALUE b);
+bool IC_value_equal(IC_VALUE a, IC_VALUE b);
+int IC_value_compare(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_eq(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_neq(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_lt(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_gt(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_le(IC_VALUE a, IC_VALUE b);
 IC_VALUE IC_ge(IC_VALUE a, IC_VALUE b);
+IC_VALUE IC_value_force(IC_VALUE value);
 IC_VALUE IC_car(IC_VALUE v);
 IC_VALUE IC_cdr(IC_VALUE v);
+IC_VALUE IC_array(IC_VALUE length, IC_LARF function);
+IC_VALUE IC_at(IC_VALUE array, IC_VALUE index);
+IC_VALUE IC_length(IC_VALUE array);
+IC_VALUE IC_record(const IC_SHAPE* shape, IC_LAR_PROTO* lar, IC_LARF* fields);
+IC_VALUE IC_field(IC_VALUE record, const int* slots, const char* name);
+IC_VALUE IC_record_update(IC_VALUE record, const int* slots, const char* name, IC_LAR_PROTO* lar, IC_LARF field);
+
+/* The same operations, for generated code, with the fl location that their errors report */
+IC_VALUE IC_add_at(IC_VALUE a, IC_VALUE b, const IC_LOC* loc);
+IC_VALUE IC_sub_at(IC_VALUE a, IC_VALUE b, const IC_LOC* loc);
+IC_VALUE IC_mul_at(IC_VALUE a, IC_VALUE b, const IC_LOC* loc);
+IC_VALUE IC_car_at(IC_VALUE v, const IC_LOC* loc);
+IC_VALUE IC_cdr_at(IC_VALUE v, const IC_LOC* loc);
+IC_VALUE IC_array_at(IC_VALUE length, IC_LARF function, const IC_LOC* loc);
+IC_VALUE IC_at_at(IC_VALUE array, IC_VALUE index, const IC_LOC* loc);
+IC_VALUE IC_length_at(IC_VALUE array, const IC_LOC* loc);
+IC_VALUE IC_field_at(IC_VALUE record, const int* slots, const char* name, const IC_LOC* loc);
+IC_VALUE IC_record_update_at(IC_VALUE record, const int* slots, const char* name, IC_LAR_PROTO* lar, IC_LARF field,
+                             const IC_LOC* loc);
+IC_VALUE IC_field_slot(IC_VALUE record, u32 shape, u8 slot, const char* name, const IC_LOC* loc);
+IC_VALUE IC_record_update_slot(IC_VALUE record, u32 shape, u8 slot, const char* name, IC_LAR_PROTO* lar,
+                               IC_LARF field, const IC_LOC* loc);
+
 void IC_value_show(IC_VALUE value, bool print_newline);
+void IC_value_stream(IC_LAR_PROTO* root);
+__attribute__((noreturn)) void IC_contract_error(const char* clause, const char* function, IC_LAR_PROTO* lar,
+                                                 const char** names);
+void IC_value_read_args(int argc, char** argv);
+IC_VALUE IC_value_read(void);
+void IC_value_read_mark(void (*mark)(IC_VALUE));
 
 #endif /* IC_VALUE_H */
@@ -4,12 +4,50 @@
 #include <stdio.h>
 #include <stdlib.h>
 
-__attribute__((noreturn)) void IC_runtime_error(const char* fmt, ...)
+/* How many of the most recent demands a runtime error shows */
+#define IC_BACKTRACE_LENGTH 16
+
+const IC_DEMAND* IC_demands[IC_DEMANDS_MAX];
+usize IC_demand_depth = 0;
+
+static void IC_backtrace(void)
+{
+	if (IC_demand_depth == 0) {
+		return;
+	}
+	fprintf(stderr, "Demanded by, most recent first:\n");
+	usize shown = IC_demand_depth < IC_BACKTRACE_LENGTH ? IC_demand_depth : IC_BACKTRACE_LENGTH;
+	for (usize i = 0; i < shown; i++) {
+		const IC_DEMAND* demand = IC_demands[(IC_demand_depth - 1 - i) % IC_DEMANDS_MAX];
+		fprintf(stderr, "\t%s at %s:%u:%u\n", demand->name, demand->loc.file, demand->loc.line, demand->loc.col);
+	}
+	if (IC_demand_depth > shown) {
+		fprintf(stderr, "\t... and %lu more\n", IC_demand_depth - shown);
+	}
+}
+
+static __attribute__((noreturn)) void IC_runtime_error_va(const IC_LOC* loc, const char* fmt, va_list args)
 {
-	va_list args;
-	va_start(args, fmt);
 	fprintf(stderr, "[Runtime error]: ");
+	if (loc != NULL) {
+		fprintf(stderr, "%s:%u:%u in %s: ", loc->file, loc->line, loc->col, loc->function);
+	}
 	vfprintf(stderr, fmt, args);
-	va_end(args);
+	fprintf(stderr, "\n");
+	IC_backtrace();
 	exit(1);
 }
+
+__attribute__((noreturn)) void IC_runtime_error(const char* fmt, ...)
+{
+	va_list args;
+	va_start(args, fmt);
+	IC_runtime_error_va(NULL, fmt, args);
+}
+
+__attribute__((noreturn)) void IC_runtime_error_at(const IC_LOC* loc, const char* fmt, ...)
+{
+	va_list args;
+	va_start(args, fmt);
+	IC_runtime_error_va(loc, fmt, args);
+}
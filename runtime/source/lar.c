@@ -1,6 +1,8 @@
-#include "value.h"
+#include "memo.h"
 
 #include <malloc.h>
+#include <stdlib.h>
+#include <stdio.h>
 #include <time.h>
 
 #define IC_GC_LIMIT 4194304
@@ -16,35 +18,105 @@ static usize IC_alloc_size = 0;
 #define IC_LAR_VALUE(_lar, _i)                                                                                         \
 	*(IC_VALUE*)((u8*)(_lar) + sizeof(IC_LAR_PROTO) + _lar->num_of_args * sizeof(IC_LARF) + _i * sizeof(IC_VALUE))
 
+static IC_LAR_PROTO* IC_lar_alloc(IC_LAR_PROTO* parent, usize size);
+static IC_LAR_PROTO* IC_record_alloc(const IC_SHAPE* shape);
 static void IC_gc(void);
 static void IC_mark(IC_LAR_PROTO* lar);
+static void IC_mark_value(IC_VALUE value);
+static void IC_mark_scan(void);
+static IC_VALUE IC_input_next(IC_LAR_PROTO* parent);
+
+static IC_VALUE* IC_roots = NULL;
+static usize IC_roots_len = 0;
+static usize IC_roots_capacity = 0;
+
+/* Marked LARs whose contents are still to be marked. They are kept on the heap, as a long list would
+ * overflow the C stack if it was marked recursively. */
+static IC_LAR_PROTO** IC_mark_stack = NULL;
+static usize IC_mark_stack_len = 0;
+static usize IC_mark_stack_capacity = 0;
+
+/* Every use of 'input' denotes the same list, so IC_gc marks its head for the whole run. It isn't on the
+ * root stack, as it can be forced inside a caller's push and pop, which would pop it. */
+static bool IC_input_started = false;
+static IC_VALUE IC_input_head;
 
 IC_LAR_PROTO* IC_lar_new(IC_LAR_PROTO* parent, u8 num_of_args, IC_LARF* args)
 {
 	usize size = sizeof(IC_LAR_PROTO) + num_of_args * sizeof(IC_LARF) + num_of_args * sizeof(IC_VALUE);
-	IC_LAR_PROTO* lar = (IC_LAR_PROTO*)malloc(size);
-	IC_alloc_size += malloc_usable_size(lar);
-
-	IC_curr_alloc += size;
-	if (IC_curr_alloc > IC_GC_LIMIT) {
-		IC_gc();
-		IC_curr_alloc = 0;
-	}
-
-	lar->parent = parent;
+	IC_LAR_PROTO* lar = IC_lar_alloc(parent, size);
 	lar->num_of_args = num_of_args;
-	lar->in_stack = 0;
-	lar->marked = 0;
 
 	for (u8 i = 0; i < num_of_args; i++) {
 		IC_LAR_THUNK(lar, i) = args[i];
 	}
-
-	lar->gc_next = IC_gc_first;
-	IC_gc_first = lar;
 	return lar;
 }
 
+/* A NULL function leaves the elements to be set by the caller */
+IC_LAR_PROTO* IC_array_new(usize length, IC_LARF function)
+{
+	usize size = sizeof(IC_ARRAY_PROTO) + length * sizeof(IC_LARF) + length * sizeof(IC_VALUE);
+	IC_LAR_PROTO* array = IC_lar_alloc(NULL, size);
+	array->num_of_args = 0;
+	array->kind = IC_LAR_ARRAY;
+	IC_ARRAY_LENGTH(array) = length;
+
+	for (usize i = 0; i < length; i++) {
+		IC_ARRAY_THUNK(array, i) = function;
+	}
+	return array;
+}
+
+/* The element's function is called like any other, with the index as its already evaluated argument */
+IC_VALUE IC_array_get(IC_LAR_PROTO* array, usize index)
+{
+	IC_LARF thunk = IC_ARRAY_THUNK(array, index);
+	if (thunk != NULL) {
+		/* Only C refers to the array while the element is computed, so it is kept alive as if it were in the stack */
+		u8 in_stack = array->in_stack;
+		array->in_stack = 1;
+
+		IC_LAR_PROTO* lar = IC_lar_new(NULL, 1, (IC_LARF[]){NULL});
+		IC_LAR_VALUE(lar, 0) = IC_INTEGER((long)index);
+		IC_VALUE value = thunk(lar);
+
+		IC_ARRAY_VALUE(array, index) = value;
+		IC_ARRAY_THUNK(array, index) = NULL;
+		array->in_stack = in_stack;
+	}
+	return IC_ARRAY_VALUE(array, index);
+}
+
+/* The fields are thunks evaluated in parent, as the arguments of a call are */
+IC_LAR_PROTO* IC_record_new(const IC_SHAPE* shape, IC_LAR_PROTO* parent, IC_LARF* fields)
+{
+	IC_LAR_PROTO* record = IC_record_alloc(shape);
+	IC_root_push(IC_RECORD(record));
+	for (u8 i = 0; i < shape->num_of_fields; i++) {
+		IC_RECORD_FIELD(record, i) = IC_lar_new(parent, 1, &fields[i]);
+	}
+	IC_root_pop(1);
+	return record;
+}
+
+/* A copy of record whose field in slot is replaced by a thunk evaluated in parent */
+IC_LAR_PROTO* IC_record_with(IC_LAR_PROTO* record, u8 slot, IC_LAR_PROTO* parent, IC_LARF field)
+{
+	IC_root_push(IC_RECORD(record));
+	IC_LAR_PROTO* updated = IC_record_alloc(IC_RECORD_SHAPE(record));
+	for (u8 i = 0; i < record->num_of_args; i++) {
+		IC_RECORD_FIELD(updated, i) = IC_RECORD_FIELD(record, i);
+	}
+	IC_RECORD_FIELD(updated, slot) = NULL;
+	IC_root_pop(1);
+
+	IC_root_push(IC_RECORD(updated));
+	IC_RECORD_FIELD(updated, slot) = IC_lar_new(parent, 1, &field);
+	IC_root_pop(1);
+	return updated;
+}
+
 IC_VALUE IC_lar_get_arg(IC_LAR_PROTO* lar, u32 arg)
 {
 	IC_LARF thunk = IC_LAR_THUNK(lar, arg);
@@ -55,20 +127,121 @@ IC_VALUE IC_lar_get_arg(IC_LAR_PROTO* lar, u32 arg)
 	return IC_LAR_VALUE(lar, arg);
 }
 
+/* A copy of lar with value as an extra, already evaluated, argument */
+IC_LAR_PROTO* IC_lar_extend(IC_LAR_PROTO* lar, IC_VALUE value)
+{
+	IC_LARF thunks[lar->num_of_args + 1];
+	for (u8 i = 0; i < lar->num_of_args; i++) {
+		thunks[i] = IC_LAR_THUNK(lar, i);
+	}
+	thunks[lar->num_of_args] = NULL;
+
+	IC_root_push(value);
+	IC_LAR_PROTO* extended = IC_lar_new(lar->parent, lar->num_of_args + 1, thunks);
+	IC_root_pop(1);
+
+	for (u8 i = 0; i < lar->num_of_args; i++) {
+		if (thunks[i] == NULL) {
+			IC_LAR_VALUE(extended, i) = IC_LAR_VALUE(lar, i);
+		}
+	}
+	IC_LAR_VALUE(extended, lar->num_of_args) = value;
+	return extended;
+}
+
+IC_VALUE IC_input(void)
+{
+	if (!IC_input_started) {
+		IC_input_head = IC_input_next(NULL);
+		IC_input_started = true;
+	}
+	return IC_input_head;
+}
+
+/* Reads a single character, the rest of the stream is read when the cdr thunk is forced */
+static IC_VALUE IC_input_next(IC_LAR_PROTO* parent)
+{
+	fflush(stdout);
+	int c = getchar();
+	if (c == EOF) {
+		return IC_ATOM(0);
+	}
+	IC_LAR_PROTO* lar = IC_lar_new(NULL, 2, (IC_LARF[]){NULL, IC_input_next});
+	IC_LAR_VALUE(lar, 0) = IC_INTEGER(c);
+	return IC_PAIR(lar);
+}
+
+/* Values that are alive without being reachable from a LAR in the stack */
+void IC_root_push(IC_VALUE value)
+{
+	if (IC_roots_len == IC_roots_capacity) {
+		IC_roots_capacity = IC_roots_capacity == 0 ? 16 : IC_roots_capacity * 2;
+		IC_roots = (IC_VALUE*)realloc(IC_roots, IC_roots_capacity * sizeof(IC_VALUE));
+	}
+	IC_roots[IC_roots_len++] = value;
+}
+
+void IC_root_pop(usize count) { IC_roots_len -= count; }
+
+usize IC_root_count(void) { return IC_roots_len; }
+
+IC_VALUE IC_root_get(usize index) { return IC_roots[index]; }
+
+void IC_root_set(usize index, IC_VALUE value) { IC_roots[index] = value; }
+
 void IC_mem_cleanup(void)
 {
+	IC_memo_cleanup();
+
 	IC_LAR_PROTO* lar = IC_gc_first;
 	while (lar != NULL) {
 		IC_LAR_PROTO* next = lar->gc_next;
 		free(lar);
 		lar = next;
 	}
+	free(IC_mark_stack);
 }
 
 double IC_get_gc_time(void) { return IC_gc_time; }
 
 usize IC_get_alloc_size(void) { return IC_alloc_size; }
 
+static IC_LAR_PROTO* IC_lar_alloc(IC_LAR_PROTO* parent, usize size)
+{
+	IC_LAR_PROTO* lar = (IC_LAR_PROTO*)malloc(size);
+	IC_alloc_size += malloc_usable_size(lar);
+
+	IC_curr_alloc += size;
+	if (IC_curr_alloc > IC_GC_LIMIT) {
+		IC_gc();
+		IC_curr_alloc = 0;
+	}
+
+	lar->parent = parent;
+	lar->in_stack = 0;
+	lar->marked = 0;
+	lar->kind = IC_LAR_FRAME;
+
+	lar->gc_next = IC_gc_first;
+	IC_gc_first = lar;
+	return lar;
+}
+
+/* The fields are left empty, a collection skips them until they are set */
+static IC_LAR_PROTO* IC_record_alloc(const IC_SHAPE* shape)
+{
+	usize size = sizeof(IC_RECORD_PROTO) + shape->num_of_fields * sizeof(IC_LAR_PROTO*);
+	IC_LAR_PROTO* record = IC_lar_alloc(NULL, size);
+	record->num_of_args = shape->num_of_fields;
+	record->kind = IC_LAR_RECORD;
+	IC_RECORD_SHAPE(record) = shape;
+
+	for (u8 i = 0; i < shape->num_of_fields; i++) {
+		IC_RECORD_FIELD(record, i) = NULL;
+	}
+	return record;
+}
+
 static void IC_gc(void)
 {
 	clock_t start = clock();
@@ -80,6 +253,15 @@ static void IC_gc(void)
 		}
 		curr = curr->gc_next;
 	}
+	for (usize i = 0; i < IC_roots_len; i++) {
+		IC_mark_value(IC_roots[i]);
+	}
+	if (IC_input_started) {
+		IC_mark_value(IC_input_head);
+	}
+	IC_value_read_mark(IC_mark_value);
+	IC_memo_mark(IC_mark_value);
+	IC_mark_scan();
 
 	curr = IC_gc_first;
 	IC_LAR_PROTO* prev = NULL;
@@ -111,15 +293,48 @@ static void IC_mark(IC_LAR_PROTO* lar)
 		return;
 	}
 	lar->marked = 1;
-	for (u8 i = 0; i < lar->num_of_args; i++) {
-		if (IC_LAR_THUNK(lar, i) != NULL) {
+	if (IC_mark_stack_len == IC_mark_stack_capacity) {
+		IC_mark_stack_capacity = IC_mark_stack_capacity == 0 ? 256 : IC_mark_stack_capacity * 2;
+		IC_mark_stack = (IC_LAR_PROTO**)realloc(IC_mark_stack, IC_mark_stack_capacity * sizeof(IC_LAR_PROTO*));
+	}
+	IC_mark_stack[IC_mark_stack_len++] = lar;
+}
+
+static void IC_mark_scan(void)
+{
+	while (IC_mark_stack_len > 0) {
+		IC_LAR_PROTO* lar = IC_mark_stack[--IC_mark_stack_len];
+		if (lar->kind == IC_LAR_ARRAY) {
+			for (usize i = 0; i < IC_ARRAY_LENGTH(lar); i++) {
+				if (IC_ARRAY_THUNK(lar, i) == NULL) {
+					IC_mark_value(IC_ARRAY_VALUE(lar, i));
+				}
+			}
 			continue;
 		}
-		IC_VALUE val = IC_LAR_VALUE(lar, i);
-		if (val.tag != IC_VALUE_PAIR) {
+		if (lar->kind == IC_LAR_RECORD) {
+			for (u8 i = 0; i < lar->num_of_args; i++) {
+				IC_mark(IC_RECORD_FIELD(lar, i));
+			}
 			continue;
 		}
-		IC_mark(val.as.pair);
+		for (u8 i = 0; i < lar->num_of_args; i++) {
+			if (IC_LAR_THUNK(lar, i) != NULL) {
+				continue;
+			}
+			IC_mark_value(IC_LAR_VALUE(lar, i));
+		}
+		IC_mark(lar->parent);
+	}
+}
+
+static void IC_mark_value(IC_VALUE value)
+{
+	if (value.tag == IC_VALUE_PAIR) {
+		IC_mark(value.as.pair);
+	} else if (value.tag == IC_VALUE_ARRAY) {
+		IC_mark(value.as.array);
+	} else if (value.tag == IC_VALUE_RECORD) {
+		IC_mark(value.as.record);
 	}
-	IC_mark(lar->parent);
 }
@@ -0,0 +1,158 @@
+#include "memo.h"
+
+#include <stdlib.h>
+
+struct IC_memo_entry {
+	IC_MEMO_ENTRY* next;
+	usize hash;
+	IC_VALUE result;
+	IC_VALUE key[];
+};
+
+/* Tables are registered when their first entry is inserted, so that the collector can find them */
+static IC_MEMO* IC_memo_tables = NULL;
+
+static usize IC_memo_hash(IC_MEMO* memo, IC_VALUE* key);
+static usize IC_memo_hash_value(IC_VALUE value);
+static void IC_memo_grow(IC_MEMO* memo);
+
+bool IC_memo_lookup(IC_MEMO* memo, IC_VALUE* key, IC_VALUE* result)
+{
+	if (memo->num_of_entries == 0) {
+		return false;
+	}
+
+	usize hash = IC_memo_hash(memo, key);
+	for (IC_MEMO_ENTRY* entry = memo->buckets[hash % memo->num_of_buckets]; entry != NULL; entry = entry->next) {
+		if (entry->hash != hash) {
+			continue;
+		}
+		u8 i = 0;
+		while (i < memo->num_of_args && IC_value_equal(entry->key[i], key[i])) {
+			i++;
+		}
+		if (i == memo->num_of_args) {
+			*result = entry->result;
+			return true;
+		}
+	}
+	return false;
+}
+
+void IC_memo_insert(IC_MEMO* memo, IC_VALUE* key, IC_VALUE result)
+{
+	if (memo->num_of_buckets == 0) {
+		memo->next = IC_memo_tables;
+		IC_memo_tables = memo;
+	}
+	if (memo->num_of_entries >= memo->num_of_buckets / 4 * 3) {
+		IC_memo_grow(memo);
+	}
+
+	IC_MEMO_ENTRY* entry = (IC_MEMO_ENTRY*)malloc(sizeof(IC_MEMO_ENTRY) + memo->num_of_args * sizeof(IC_VALUE));
+	entry->hash = IC_memo_hash(memo, key);
+	entry->result = result;
+	for (u8 i = 0; i < memo->num_of_args; i++) {
+		entry->key[i] = key[i];
+	}
+
+	IC_MEMO_ENTRY** bucket = &memo->buckets[entry->hash % memo->num_of_buckets];
+	entry->next = *bucket;
+	*bucket = entry;
+	memo->num_of_entries++;
+}
+
+void IC_memo_mark(void (*mark)(IC_VALUE))
+{
+	for (IC_MEMO* memo = IC_memo_tables; memo != NULL; memo = memo->next) {
+		for (usize i = 0; i < memo->num_of_buckets; i++) {
+			for (IC_MEMO_ENTRY* entry = memo->buckets[i]; entry != NULL; entry = entry->next) {
+				for (u8 j = 0; j < memo->num_of_args; j++) {
+					mark(entry->key[j]);
+				}
+				mark(entry->result);
+			}
+		}
+	}
+}
+
+void IC_memo_cleanup(void)
+{
+	IC_MEMO* memo = IC_memo_tables;
+	while (memo != NULL) {
+		for (usize i = 0; i < memo->num_of_buckets; i++) {
+			IC_MEMO_ENTRY* entry = memo->buckets[i];
+			while (entry != NULL) {
+				IC_MEMO_ENTRY* next = entry->next;
+				free(entry);
+				entry = next;
+			}
+		}
+		free(memo->buckets);
+		IC_MEMO* next = memo->next;
+		*memo = (IC_MEMO)IC_MEMO_INIT(memo->num_of_args);
+		memo = next;
+	}
+	IC_memo_tables = NULL;
+}
+
+static usize IC_memo_hash(IC_MEMO* memo, IC_VALUE* key)
+{
+	usize hash = 14695981039346656037UL;
+	for (u8 i = 0; i < memo->num_of_args; i++) {
+		hash = (hash ^ IC_memo_hash_value(key[i])) * 1099511628211UL;
+	}
+	return hash;
+}
+
+/* The values are fully evaluated, so the arguments of pairs and fields of records hold values instead of thunks,
+ * as do the elements of arrays */
+static usize IC_memo_hash_value(IC_VALUE value)
+{
+	usize hash = 14695981039346656037UL;
+	while (value.tag == IC_VALUE_PAIR) {
+		hash = (hash ^ IC_memo_hash_value(IC_LAR_VALUE(value.as.pair, 0))) * 1099511628211UL;
+		value = IC_LAR_VALUE(value.as.pair, 1);
+	}
+	switch (value.tag) {
+	case IC_VALUE_INTEGER:
+		hash = (hash ^ (usize)value.as.integer) * 1099511628211UL;
+		break;
+	case IC_VALUE_ATOM:
+		hash = (hash ^ value.as.atom ^ 0x5bd1e995UL) * 1099511628211UL;
+		break;
+	case IC_VALUE_ARRAY:
+		for (usize i = 0; i < IC_ARRAY_LENGTH(value.as.array); i++) {
+			hash = (hash ^ IC_memo_hash_value(IC_ARRAY_VALUE(value.as.array, i))) * 1099511628211UL;
+		}
+		break;
+	case IC_VALUE_RECORD:
+		hash = (hash ^ IC_RECORD_SHAPE(value.as.record)->id) * 1099511628211UL;
+		for (u8 i = 0; i < value.as.record->num_of_args; i++) {
+			IC_LAR_PROTO* field = IC_RECORD_FIELD(value.as.record, i);
+			hash = (hash ^ IC_memo_hash_value(IC_LAR_VALUE(field, 0))) * 1099511628211UL;
+		}
+		break;
+	default:
+		break;
+	}
+	return hash;
+}
+
+static void IC_memo_grow(IC_MEMO* memo)
+{
+	usize num_of_buckets = memo->num_of_buckets == 0 ? 64 : memo->num_of_buckets * 2;
+	IC_MEMO_ENTRY** buckets = (IC_MEMO_ENTRY**)calloc(num_of_buckets, sizeof(IC_MEMO_ENTRY*));
+	for (usize i = 0; i < memo->num_of_buckets; i++) {
+		IC_MEMO_ENTRY* entry = memo->buckets[i];
+		while (entry != NULL) {
+			IC_MEMO_ENTRY* next = entry->next;
+			entry->next = buckets[entry->hash % num_of_buckets];
+			buckets[entry->hash % num_of_buckets] = entry;
+			entry = next;
+		}
+	}
+	free(memo->buckets);
+	memo->buckets = buckets;
+	memo->num_of_buckets = num_of_buckets;
+}
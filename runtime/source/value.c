@@ -1,133 +1,759 @@
+#define _POSIX_C_SOURCE 200809L
+
 #include "value.h"
 
+#include <ctype.h>
 #include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+const char** IC_atom_names = NULL;
+static usize IC_atom_count = 0;
+static usize IC_atom_capacity = 0;
+
+static const char* IC_read_path = NULL;
+static bool IC_read_started = false;
+static IC_VALUE IC_read_result;
+
+typedef struct {
+	const char* source;
+	const char* curr;
+	const char* path;
+} IC_READER;
 
 static const char* IC_value_show_type(IC_VALUE value);
+static int IC_shape_compare(const IC_SHAPE* a, const IC_SHAPE* b);
+static IC_VALUE IC_record_get(IC_VALUE record, u8 slot);
+static void IC_value_write(FILE* out, IC_VALUE value);
+static void IC_value_write_evaluated(FILE* out, IC_VALUE value, int depth);
+static void IC_value_write_slot(FILE* out, IC_LARF thunk, IC_VALUE value, int depth);
+static IC_VALUE IC_read(IC_READER* reader);
+static void IC_read_skip_whitespace(IC_READER* reader);
+__attribute__((noreturn)) static void IC_read_error(IC_READER* reader, const char* message);
 
-IC_VALUE IC_add(IC_VALUE a, IC_VALUE b)
+/* The compile time atoms are used in place, the table is copied the first time it needs to grow */
+void IC_atoms_init(const char** names, usize count)
 {
-	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return IC_INTEGER(a.as.integer + b.as.integer);
-	} else {
-		IC_runtime_error("cannot add %s and %s", IC_value_show_type(a), IC_value_show_type(b));
-	}
+	IC_atom_names = names;
+	IC_atom_count = count;
+	IC_atom_capacity = 0;
 }
 
-IC_VALUE IC_sub(IC_VALUE a, IC_VALUE b)
+usize IC_atom_intern(const char* name, usize len)
 {
-	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return IC_INTEGER(a.as.integer - b.as.integer);
-	} else {
-		IC_runtime_error("cannot subtract %s and %s", IC_value_show_type(a), IC_value_show_type(b));
+	for (usize i = 0; i < IC_atom_count; i++) {
+		if (strncmp(IC_atom_names[i], name, len) == 0 && IC_atom_names[i][len] == '\0') {
+			return i;
+		}
+	}
+
+	if (IC_atom_count == IC_atom_capacity || IC_atom_capacity == 0) {
+		usize capacity = IC_atom_count < 8 ? 16 : IC_atom_count * 2;
+		const char** names = (const char**)malloc(capacity * sizeof(const char*));
+		memcpy(names, IC_atom_names, IC_atom_count * sizeof(const char*));
+		if (IC_atom_capacity != 0) {
+			free(IC_atom_names);
+		}
+		IC_atom_names = names;
+		IC_atom_capacity = capacity;
 	}
+
+	char* copy = (char*)malloc(len + 1);
+	memcpy(copy, name, len);
+	copy[len] = '\0';
+	IC_atom_names[IC_atom_count] = copy;
+	return IC_atom_count++;
 }
 
-IC_VALUE IC_mul(IC_VALUE a, IC_VALUE b)
+IC_VALUE IC_atom_named(const char* name) { return IC_ATOM(IC_atom_intern(name, strlen(name))); }
+
+const char* IC_atom_name(IC_VALUE atom)
 {
-	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return IC_INTEGER(a.as.integer * b.as.integer);
-	} else {
-		IC_runtime_error("cannot multiply %s and %s", IC_value_show_type(a), IC_value_show_type(b));
+	if (atom.tag != IC_VALUE_ATOM) {
+		IC_runtime_error("cannot get the name of %s", IC_value_show_type(atom));
 	}
+	return IC_atom_names[atom.as.atom];
 }
 
-IC_VALUE IC_eq(IC_VALUE a, IC_VALUE b)
+IC_VALUE IC_cons(IC_VALUE car, IC_VALUE cdr)
+{
+	IC_LAR_PROTO* lar = IC_lar_new(NULL, 2, (IC_LARF[]){NULL, NULL});
+	IC_LAR_VALUE(lar, 0) = car;
+	IC_LAR_VALUE(lar, 1) = cdr;
+	return IC_PAIR(lar);
+}
+
+IC_VALUE IC_add_at(IC_VALUE a, IC_VALUE b, const IC_LOC* loc)
 {
 	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return a.as.integer == b.as.integer ? IC_ATOM(1) : IC_ATOM(2);
-	} else if (a.tag == IC_VALUE_ATOM && b.tag == IC_VALUE_ATOM) {
-		return a.as.atom == b.as.atom ? IC_ATOM(1) : IC_ATOM(2);
+		return IC_INTEGER(a.as.integer + b.as.integer);
 	} else {
-		return IC_ATOM(2);
+		IC_runtime_error_at(loc, "cannot add %s and %s", IC_value_show_type(a), IC_value_show_type(b));
 	}
 }
 
-IC_VALUE IC_neq(IC_VALUE a, IC_VALUE b)
+IC_VALUE IC_add(IC_VALUE a, IC_VALUE b) { return IC_add_at(a, b, NULL); }
+
+IC_VALUE IC_sub_at(IC_VALUE a, IC_VALUE b, const IC_LOC* loc)
 {
 	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return a.as.integer != b.as.integer ? IC_ATOM(1) : IC_ATOM(2);
-	} else if (a.tag == IC_VALUE_ATOM && b.tag == IC_VALUE_ATOM) {
-		return a.as.atom != b.as.atom ? IC_ATOM(1) : IC_ATOM(2);
+		return IC_INTEGER(a.as.integer - b.as.integer);
 	} else {
-		return IC_ATOM(1);
+		IC_runtime_error_at(loc, "cannot subtract %s and %s", IC_value_show_type(a), IC_value_show_type(b));
 	}
 }
 
-IC_VALUE IC_lt(IC_VALUE a, IC_VALUE b)
+IC_VALUE IC_sub(IC_VALUE a, IC_VALUE b) { return IC_sub_at(a, b, NULL); }
+
+IC_VALUE IC_mul_at(IC_VALUE a, IC_VALUE b, const IC_LOC* loc)
 {
 	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return a.as.integer < b.as.integer ? IC_ATOM(1) : IC_ATOM(2);
+		return IC_INTEGER(a.as.integer * b.as.integer);
 	} else {
-		IC_runtime_error("cannot compare %s and %s", IC_value_show_type(a), IC_value_show_type(b));
+		IC_runtime_error_at(loc, "cannot multiply %s and %s", IC_value_show_type(a), IC_value_show_type(b));
 	}
 }
 
-IC_VALUE IC_gt(IC_VALUE a, IC_VALUE b)
+IC_VALUE IC_mul(IC_VALUE a, IC_VALUE b) { return IC_mul_at(a, b, NULL); }
+
+/* Forcing can collect, so the operands of a comparison are rooted in the slots from root on. The slots
+ * follow the lists being walked, and are read again after each force. */
+#define IC_A IC_root_get(root)
+#define IC_B IC_root_get(root + 1)
+
+static bool IC_value_equal_rooted(usize root)
 {
-	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return a.as.integer > b.as.integer ? IC_ATOM(1) : IC_ATOM(2);
-	} else {
-		IC_runtime_error("cannot compare %s and %s", IC_value_show_type(a), IC_value_show_type(b));
+	while (IC_A.tag == IC_VALUE_PAIR && IC_B.tag == IC_VALUE_PAIR) {
+		if (IC_A.as.pair == IC_B.as.pair) {
+			return true;
+		}
+		/* Once forced, the car is held by its pair, which stays rooted */
+		IC_VALUE car = IC_lar_get_arg(IC_A.as.pair, 0);
+		if (!IC_value_equal(car, IC_lar_get_arg(IC_B.as.pair, 0))) {
+			return false;
+		}
+		IC_root_set(root, IC_lar_get_arg(IC_A.as.pair, 1));
+		IC_root_set(root + 1, IC_lar_get_arg(IC_B.as.pair, 1));
+	}
+	if (IC_A.tag != IC_B.tag) {
+		return false;
+	}
+	switch (IC_A.tag) {
+	case IC_VALUE_INTEGER:
+		return IC_A.as.integer == IC_B.as.integer;
+	case IC_VALUE_ATOM:
+		return IC_A.as.atom == IC_B.as.atom;
+	case IC_VALUE_ARRAY: {
+		if (IC_A.as.array == IC_B.as.array) {
+			return true;
+		}
+		usize length = IC_ARRAY_LENGTH(IC_A.as.array);
+		if (length != IC_ARRAY_LENGTH(IC_B.as.array)) {
+			return false;
+		}
+		for (usize i = 0; i < length; i++) {
+			IC_VALUE element = IC_array_get(IC_A.as.array, i);
+			if (!IC_value_equal(element, IC_array_get(IC_B.as.array, i))) {
+				return false;
+			}
+		}
+		return true;
+	}
+	case IC_VALUE_RECORD: {
+		if (IC_A.as.record == IC_B.as.record) {
+			return true;
+		}
+		if (IC_RECORD_SHAPE(IC_A.as.record)->id != IC_RECORD_SHAPE(IC_B.as.record)->id) {
+			return false;
+		}
+		for (u8 i = 0; i < IC_A.as.record->num_of_args; i++) {
+			IC_VALUE field = IC_lar_get_arg(IC_RECORD_FIELD(IC_A.as.record, i), 0);
+			if (!IC_value_equal(field, IC_lar_get_arg(IC_RECORD_FIELD(IC_B.as.record, i), 0))) {
+				return false;
+			}
+		}
+		return true;
+	}
+	default:
+		return false;
 	}
 }
 
-IC_VALUE IC_le(IC_VALUE a, IC_VALUE b)
+static int IC_value_compare_rooted(usize root)
 {
-	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return a.as.integer <= b.as.integer ? IC_ATOM(1) : IC_ATOM(2);
-	} else {
-		IC_runtime_error("cannot use 'lq?' on %s and %s", IC_value_show_type(a), IC_value_show_type(b));
+	while (IC_A.tag == IC_VALUE_PAIR && IC_B.tag == IC_VALUE_PAIR) {
+		if (IC_A.as.pair == IC_B.as.pair) {
+			return 0;
+		}
+		IC_VALUE car = IC_lar_get_arg(IC_A.as.pair, 0);
+		int order = IC_value_compare(car, IC_lar_get_arg(IC_B.as.pair, 0));
+		if (order != 0) {
+			return order;
+		}
+		IC_root_set(root, IC_lar_get_arg(IC_A.as.pair, 1));
+		IC_root_set(root + 1, IC_lar_get_arg(IC_B.as.pair, 1));
+	}
+	if (IC_A.tag != IC_B.tag) {
+		return IC_A.tag < IC_B.tag ? -1 : 1;
+	}
+	switch (IC_A.tag) {
+	case IC_VALUE_INTEGER:
+		return IC_A.as.integer < IC_B.as.integer ? -1 : IC_A.as.integer > IC_B.as.integer;
+	case IC_VALUE_ATOM:
+		return IC_A.as.atom == IC_B.as.atom ? 0 : strcmp(IC_atom_names[IC_A.as.atom], IC_atom_names[IC_B.as.atom]);
+	case IC_VALUE_ARRAY: {
+		usize a_length = IC_ARRAY_LENGTH(IC_A.as.array);
+		usize b_length = IC_ARRAY_LENGTH(IC_B.as.array);
+		for (usize i = 0; i < a_length && i < b_length && IC_A.as.array != IC_B.as.array; i++) {
+			IC_VALUE element = IC_array_get(IC_A.as.array, i);
+			int order = IC_value_compare(element, IC_array_get(IC_B.as.array, i));
+			if (order != 0) {
+				return order;
+			}
+		}
+		return a_length < b_length ? -1 : a_length > b_length;
+	}
+	case IC_VALUE_RECORD: {
+		if (IC_A.as.record == IC_B.as.record) {
+			return 0;
+		}
+		int shape = IC_shape_compare(IC_RECORD_SHAPE(IC_A.as.record), IC_RECORD_SHAPE(IC_B.as.record));
+		if (shape != 0) {
+			return shape;
+		}
+		for (u8 i = 0; i < IC_A.as.record->num_of_args; i++) {
+			IC_VALUE field = IC_lar_get_arg(IC_RECORD_FIELD(IC_A.as.record, i), 0);
+			int order = IC_value_compare(field, IC_lar_get_arg(IC_RECORD_FIELD(IC_B.as.record, i), 0));
+			if (order != 0) {
+				return order;
+			}
+		}
+		return 0;
+	}
+	default:
+		return 0;
 	}
 }
 
-IC_VALUE IC_ge(IC_VALUE a, IC_VALUE b)
+#undef IC_A
+#undef IC_B
+
+/* Pairs are equal if their cars and cdrs are, which are only evaluated as far as needed to tell */
+bool IC_value_equal(IC_VALUE a, IC_VALUE b)
 {
-	if (a.tag == IC_VALUE_INTEGER && b.tag == IC_VALUE_INTEGER) {
-		return a.as.integer >= b.as.integer ? IC_ATOM(1) : IC_ATOM(2);
-	} else {
-		IC_runtime_error("cannot use 'gq?' on %s and %s", IC_value_show_type(a), IC_value_show_type(b));
+	usize root = IC_root_count();
+	IC_root_push(a);
+	IC_root_push(b);
+	bool equal = IC_value_equal_rooted(root);
+	IC_root_pop(2);
+	return equal;
+}
+
+/* Integers come before atoms, which come before pairs, then arrays and then records. Atoms are ordered by name,
+ * pairs lexicographically by their car and then their cdr, and arrays lexicographically by their elements.
+ * Records are ordered by their field names first, and then lexicographically by their fields. */
+int IC_value_compare(IC_VALUE a, IC_VALUE b)
+{
+	usize root = IC_root_count();
+	IC_root_push(a);
+	IC_root_push(b);
+	int order = IC_value_compare_rooted(root);
+	IC_root_pop(2);
+	return order;
+}
+
+IC_VALUE IC_eq(IC_VALUE a, IC_VALUE b) { return IC_value_equal(a, b) ? IC_ATOM(1) : IC_ATOM(2); }
+
+IC_VALUE IC_neq(IC_VALUE a, IC_VALUE b) { return IC_value_equal(a, b) ? IC_ATOM(2) : IC_ATOM(1); }
+
+IC_VALUE IC_lt(IC_VALUE a, IC_VALUE b) { return IC_value_compare(a, b) < 0 ? IC_ATOM(1) : IC_ATOM(2); }
+
+IC_VALUE IC_gt(IC_VALUE a, IC_VALUE b) { return IC_value_compare(a, b) > 0 ? IC_ATOM(1) : IC_ATOM(2); }
+
+IC_VALUE IC_le(IC_VALUE a, IC_VALUE b) { return IC_value_compare(a, b) <= 0 ? IC_ATOM(1) : IC_ATOM(2); }
+
+IC_VALUE IC_ge(IC_VALUE a, IC_VALUE b) { return IC_value_compare(a, b) >= 0 ? IC_ATOM(1) : IC_ATOM(2); }
+
+/* Evaluates value completely, leaving no thunks in the pairs, arrays and records it consists of */
+IC_VALUE IC_value_force(IC_VALUE value)
+{
+	IC_VALUE curr = value;
+	while (curr.tag == IC_VALUE_PAIR) {
+		IC_value_force(IC_lar_get_arg(curr.as.pair, 0));
+		curr = IC_lar_get_arg(curr.as.pair, 1);
 	}
+	if (curr.tag == IC_VALUE_ARRAY) {
+		for (usize i = 0; i < IC_ARRAY_LENGTH(curr.as.array); i++) {
+			IC_value_force(IC_array_get(curr.as.array, i));
+		}
+	} else if (curr.tag == IC_VALUE_RECORD) {
+		for (u8 i = 0; i < curr.as.record->num_of_args; i++) {
+			IC_value_force(IC_lar_get_arg(IC_RECORD_FIELD(curr.as.record, i), 0));
+		}
+	}
+	return value;
 }
 
-IC_VALUE IC_car(IC_VALUE v)
+IC_VALUE IC_car_at(IC_VALUE v, const IC_LOC* loc)
 {
 	if (v.tag != IC_VALUE_PAIR) {
-		IC_runtime_error("cannot use 'car' on %s", IC_value_show_type(v));
+		IC_runtime_error_at(loc, "cannot use 'car' on %s", IC_value_show_type(v));
 	}
 	return IC_lar_get_arg(v.as.pair, 0);
 }
 
-IC_VALUE IC_cdr(IC_VALUE v)
+IC_VALUE IC_car(IC_VALUE v) { return IC_car_at(v, NULL); }
+
+IC_VALUE IC_cdr_at(IC_VALUE v, const IC_LOC* loc)
 {
 	if (v.tag != IC_VALUE_PAIR) {
-		IC_runtime_error("cannot use 'cdr' on %s", IC_value_show_type(v));
+		IC_runtime_error_at(loc, "cannot use 'cdr' on %s", IC_value_show_type(v));
 	}
 	return IC_lar_get_arg(v.as.pair, 1);
 }
 
+IC_VALUE IC_cdr(IC_VALUE v) { return IC_cdr_at(v, NULL); }
+
+/* The elements are only computed when they are indexed */
+IC_VALUE IC_array_at(IC_VALUE length, IC_LARF function, const IC_LOC* loc)
+{
+	if (length.tag != IC_VALUE_INTEGER) {
+		IC_runtime_error_at(loc, "cannot use %s as the length of an array", IC_value_show_type(length));
+	}
+	if (length.as.integer < 0) {
+		IC_runtime_error_at(loc, "cannot create an array of negative length %ld", length.as.integer);
+	}
+	return IC_ARRAY(IC_array_new((usize)length.as.integer, function));
+}
+
+IC_VALUE IC_array(IC_VALUE length, IC_LARF function) { return IC_array_at(length, function, NULL); }
+
+IC_VALUE IC_at_at(IC_VALUE array, IC_VALUE index, const IC_LOC* loc)
+{
+	if (array.tag != IC_VALUE_ARRAY) {
+		IC_runtime_error_at(loc, "cannot use 'at' on %s", IC_value_show_type(array));
+	}
+	if (index.tag != IC_VALUE_INTEGER) {
+		IC_runtime_error_at(loc, "cannot index an array with %s", IC_value_show_type(index));
+	}
+	usize length = IC_ARRAY_LENGTH(array.as.array);
+	if (index.as.integer < 0 || (usize)index.as.integer >= length) {
+		IC_runtime_error_at(loc, "index %ld is out of bounds for an array of length %lu", index.as.integer, length);
+	}
+	return IC_array_get(array.as.array, (usize)index.as.integer);
+}
+
+IC_VALUE IC_at(IC_VALUE array, IC_VALUE index) { return IC_at_at(array, index, NULL); }
+
+IC_VALUE IC_length_at(IC_VALUE array, const IC_LOC* loc)
+{
+	if (array.tag != IC_VALUE_ARRAY) {
+		IC_runtime_error_at(loc, "cannot use 'length' on %s", IC_value_show_type(array));
+	}
+	return IC_INTEGER((long)IC_ARRAY_LENGTH(array.as.array));
+}
+
+IC_VALUE IC_length(IC_VALUE array) { return IC_length_at(array, NULL); }
+
+IC_VALUE IC_record(const IC_SHAPE* shape, IC_LAR_PROTO* lar, IC_LARF* fields)
+{
+	return IC_RECORD(IC_record_new(shape, lar, fields));
+}
+
+/* slots maps the id of each shape to the slot of the field, or -1 if the shape doesn't have it */
+IC_VALUE IC_field_at(IC_VALUE record, const int* slots, const char* name, const IC_LOC* loc)
+{
+	if (record.tag != IC_VALUE_RECORD) {
+		IC_runtime_error_at(loc, "cannot get field '%s' of %s", name, IC_value_show_type(record));
+	}
+	int slot = slots[IC_RECORD_SHAPE(record.as.record)->id];
+	if (slot < 0) {
+		IC_runtime_error_at(loc, "record has no field '%s'", name);
+	}
+	return IC_record_get(record, (u8)slot);
+}
+
+IC_VALUE IC_field(IC_VALUE record, const int* slots, const char* name) { return IC_field_at(record, slots, name, NULL); }
+
+IC_VALUE IC_record_update_at(IC_VALUE record, const int* slots, const char* name, IC_LAR_PROTO* lar, IC_LARF field,
+                             const IC_LOC* loc)
+{
+	if (record.tag != IC_VALUE_RECORD) {
+		IC_runtime_error_at(loc, "cannot update field '%s' of %s", name, IC_value_show_type(record));
+	}
+	int slot = slots[IC_RECORD_SHAPE(record.as.record)->id];
+	if (slot < 0) {
+		IC_runtime_error_at(loc, "record has no field '%s'", name);
+	}
+	return IC_RECORD(IC_record_with(record.as.record, (u8)slot, lar, field));
+}
+
+IC_VALUE IC_record_update(IC_VALUE record, const int* slots, const char* name, IC_LAR_PROTO* lar, IC_LARF field)
+{
+	return IC_record_update_at(record, slots, name, lar, field, NULL);
+}
+
+/* A field resolved at compile time, which is at slot in the records of shape, and in no others */
+IC_VALUE IC_field_slot(IC_VALUE record, u32 shape, u8 slot, const char* name, const IC_LOC* loc)
+{
+	if (record.tag != IC_VALUE_RECORD) {
+		IC_runtime_error_at(loc, "cannot get field '%s' of %s", name, IC_value_show_type(record));
+	}
+	if (IC_RECORD_SHAPE(record.as.record)->id != shape) {
+		IC_runtime_error_at(loc, "record has no field '%s'", name);
+	}
+	return IC_record_get(record, slot);
+}
+
+IC_VALUE IC_record_update_slot(IC_VALUE record, u32 shape, u8 slot, const char* name, IC_LAR_PROTO* lar,
+                               IC_LARF field, const IC_LOC* loc)
+{
+	if (record.tag != IC_VALUE_RECORD) {
+		IC_runtime_error_at(loc, "cannot update field '%s' of %s", name, IC_value_show_type(record));
+	}
+	if (IC_RECORD_SHAPE(record.as.record)->id != shape) {
+		IC_runtime_error_at(loc, "record has no field '%s'", name);
+	}
+	return IC_RECORD(IC_record_with(record.as.record, slot, lar, field));
+}
+
+static IC_VALUE IC_record_get(IC_VALUE record, u8 slot)
+{
+	/* The record may only be referred to from C, and has to survive the field's evaluation */
+	IC_root_push(record);
+	IC_VALUE value = IC_lar_get_arg(IC_RECORD_FIELD(record.as.record, slot), 0);
+	IC_root_pop(1);
+	return value;
+}
+
 void IC_value_show(IC_VALUE value, bool print_newline)
+{
+	IC_value_write(stdout, value);
+	if (print_newline) {
+		printf("\n");
+	}
+}
+
+static void IC_value_write(FILE* out, IC_VALUE value)
 {
 	switch (value.tag) {
 	case IC_VALUE_INTEGER: {
-		printf("%ld", value.as.integer);
+		fprintf(out, "%ld", value.as.integer);
 		break;
 	}
 	case IC_VALUE_ATOM: {
-		printf("'%s", IC_atom_names[value.as.atom]);
+		fprintf(out, "'%s", IC_atom_names[value.as.atom]);
 		break;
 	}
 	case IC_VALUE_PAIR: {
-		printf("(");
-		IC_value_show(IC_lar_get_arg(value.as.pair, 0), false);
-		printf(" . ");
-		IC_value_show(IC_lar_get_arg(value.as.pair, 1), false);
-		printf(")");
+		fprintf(out, "(");
+		IC_value_write(out, IC_lar_get_arg(value.as.pair, 0));
+		fprintf(out, " . ");
+		IC_value_write(out, IC_lar_get_arg(value.as.pair, 1));
+		fprintf(out, ")");
 		break;
 	}
+	case IC_VALUE_ARRAY: {
+		fprintf(out, "[");
+		for (usize i = 0; i < IC_ARRAY_LENGTH(value.as.array); i++) {
+			if (i > 0) {
+				fprintf(out, " ");
+			}
+			IC_value_write(out, IC_array_get(value.as.array, i));
+		}
+		fprintf(out, "]");
+		break;
 	}
-	if (print_newline) {
-		printf("\n");
+	case IC_VALUE_RECORD: {
+		const IC_SHAPE* shape = IC_RECORD_SHAPE(value.as.record);
+		fprintf(out, "{");
+		for (u8 i = 0; i < shape->num_of_fields; i++) {
+			fprintf(out, i > 0 ? ", %s = " : "%s = ", shape->fields[i]);
+			IC_value_write(out, IC_lar_get_arg(IC_RECORD_FIELD(value.as.record, i), 0));
+		}
+		fprintf(out, "}");
+		break;
+	}
+	}
+}
+
+/* Arguments are shown this many levels deep, and arrays this many elements long */
+#define IC_CONTRACT_DEPTH 8
+#define IC_CONTRACT_LENGTH 16
+
+/* The names are those of the arguments in lar. They are shown as far as they have been evaluated,
+ * as forcing them could fail or never end, with '_' for what hasn't. */
+void IC_contract_error(const char* clause, const char* function, IC_LAR_PROTO* lar, const char** names)
+{
+	char* message;
+	size_t len;
+	FILE* out = open_memstream(&message, &len);
+	fprintf(out, "%s of %s violated", clause, function);
+	for (u8 i = 0; i < lar->num_of_args; i++) {
+		fprintf(out, "%s%s = ", i == 0 ? " with " : ", ", names[i]);
+		IC_value_write_slot(out, IC_LAR_THUNK(lar, i), IC_LAR_VALUE(lar, i), IC_CONTRACT_DEPTH);
+	}
+	fclose(out);
+	IC_runtime_error("%s", message);
+}
+
+static void IC_value_write_slot(FILE* out, IC_LARF thunk, IC_VALUE value, int depth)
+{
+	if (thunk != NULL) {
+		fprintf(out, "_");
+	} else {
+		IC_value_write_evaluated(out, value, depth);
+	}
+}
+
+/* Like IC_value_write, without forcing anything, and with '...' past the depth */
+static void IC_value_write_evaluated(FILE* out, IC_VALUE value, int depth)
+{
+	if (depth == 0 && value.tag != IC_VALUE_INTEGER && value.tag != IC_VALUE_ATOM) {
+		fprintf(out, "...");
+		return;
+	}
+	switch (value.tag) {
+	case IC_VALUE_INTEGER:
+	case IC_VALUE_ATOM: {
+		IC_value_write(out, value);
+		break;
+	}
+	case IC_VALUE_PAIR: {
+		IC_LAR_PROTO* pair = value.as.pair;
+		fprintf(out, "(");
+		IC_value_write_slot(out, IC_LAR_THUNK(pair, 0), IC_LAR_VALUE(pair, 0), depth - 1);
+		fprintf(out, " . ");
+		IC_value_write_slot(out, IC_LAR_THUNK(pair, 1), IC_LAR_VALUE(pair, 1), depth - 1);
+		fprintf(out, ")");
+		break;
+	}
+	case IC_VALUE_ARRAY: {
+		IC_LAR_PROTO* array = value.as.array;
+		fprintf(out, "[");
+		for (usize i = 0; i < IC_ARRAY_LENGTH(array); i++) {
+			if (i > 0) {
+				fprintf(out, " ");
+			}
+			if (i == IC_CONTRACT_LENGTH) {
+				fprintf(out, "...");
+				break;
+			}
+			IC_value_write_slot(out, IC_ARRAY_THUNK(array, i), IC_ARRAY_VALUE(array, i), depth - 1);
+		}
+		fprintf(out, "]");
+		break;
+	}
+	case IC_VALUE_RECORD: {
+		const IC_SHAPE* shape = IC_RECORD_SHAPE(value.as.record);
+		fprintf(out, "{");
+		for (u8 i = 0; i < shape->num_of_fields; i++) {
+			IC_LAR_PROTO* field = IC_RECORD_FIELD(value.as.record, i);
+			fprintf(out, i > 0 ? ", %s = " : "%s = ", shape->fields[i]);
+			IC_value_write_slot(out, IC_LAR_THUNK(field, 0), IC_LAR_VALUE(field, 0), depth - 1);
+		}
+		fprintf(out, "}");
+		break;
+	}
+	}
+}
+
+/* Writes the list of characters held in the first slot of root to stdout as it is forced.
+ * The slot is advanced along the list, so cells that have been written can be collected. */
+void IC_value_stream(IC_LAR_PROTO* root)
+{
+	IC_VALUE value = IC_LAR_VALUE(root, 0);
+	while (value.tag == IC_VALUE_PAIR) {
+		IC_VALUE c = IC_lar_get_arg(value.as.pair, 0);
+		if (c.tag != IC_VALUE_INTEGER) {
+			IC_runtime_error("cannot write %s as a character", IC_value_show_type(c));
+		}
+		putchar((int)c.as.integer);
+		value = IC_lar_get_arg(value.as.pair, 1);
+		IC_LAR_VALUE(root, 0) = value;
+	}
+	if (value.tag != IC_VALUE_ATOM || value.as.atom != 0) {
+		IC_runtime_error("cannot write %s as the end of a character list", IC_value_show_type(value));
+	}
+	fflush(stdout);
+}
+
+void IC_value_read_args(int argc, char** argv)
+{
+	for (int i = 1; i < argc; i++) {
+		if (strcmp(argv[i], "--input") == 0) {
+			if (i + 1 == argc) {
+				IC_runtime_error("--input requires a path");
+			}
+			IC_read_path = argv[++i];
+		}
+	}
+}
+
+/* Reads a value written in the syntax of IC_value_show from the --input file, or stdin if there is none */
+IC_VALUE IC_value_read(void)
+{
+	if (IC_read_started) {
+		return IC_read_result;
+	}
+
+	FILE* file = IC_read_path != NULL ? fopen(IC_read_path, "r") : stdin;
+	const char* path = IC_read_path != NULL ? IC_read_path : "<stdin>";
+	if (file == NULL) {
+		IC_runtime_error("cannot open %s", path);
+	}
+
+	usize len = 0;
+	usize capacity = 4096;
+	char* source = (char*)malloc(capacity);
+	usize n;
+	while ((n = fread(source + len, 1, capacity - len - 1, file)) > 0) {
+		len += n;
+		if (len + 1 == capacity) {
+			capacity *= 2;
+			source = (char*)realloc(source, capacity);
+		}
+	}
+	source[len] = '\0';
+	if (file != stdin) {
+		fclose(file);
+	}
+
+	IC_READER reader = {source, source, path};
+	IC_read_result = IC_read(&reader);
+	IC_read_skip_whitespace(&reader);
+	if (*reader.curr != '\0') {
+		IC_read_error(&reader, "expected end of input");
+	}
+	free(source);
+
+	IC_read_started = true;
+	return IC_read_result;
+}
+
+/* Every use of 'readValue' denotes the same value, so it is kept alive for the whole run. It isn't on the
+ * root stack, as it can be read inside a caller's push and pop, which would pop it. */
+void IC_value_read_mark(void (*mark)(IC_VALUE))
+{
+	if (IC_read_started) {
+		mark(IC_read_result);
+	}
+}
+
+static void IC_read_expect(IC_READER* reader, char c, const char* message)
+{
+	IC_read_skip_whitespace(reader);
+	if (*reader->curr != c) {
+		IC_read_error(reader, message);
 	}
+	reader->curr++;
+}
+
+static IC_VALUE IC_read(IC_READER* reader)
+{
+	IC_read_skip_whitespace(reader);
+
+	char c = *reader->curr;
+	if (c == '-' || isdigit((unsigned char)c)) {
+		char* end;
+		long integer = strtol(reader->curr, &end, 10);
+		if (end == reader->curr || (c == '-' && end == reader->curr + 1)) {
+			IC_read_error(reader, "expected a number");
+		}
+		reader->curr = end;
+		return IC_INTEGER(integer);
+	} else if (c == '\'') {
+		const char* start = ++reader->curr;
+		if (!isalpha((unsigned char)*reader->curr)) {
+			IC_read_error(reader, "expected alphabetic character after '");
+		}
+		while (isalnum((unsigned char)*reader->curr)) {
+			reader->curr++;
+		}
+		return IC_ATOM(IC_atom_intern(start, reader->curr - start));
+	} else if (c == '(') {
+		/* Lists nest to the right, so the cars of a chain of pairs are collected iteratively */
+		usize len = 0;
+		usize capacity = 16;
+		IC_VALUE* cars = (IC_VALUE*)malloc(capacity * sizeof(IC_VALUE));
+		do {
+			reader->curr++;
+			if (len == capacity) {
+				capacity *= 2;
+				cars = (IC_VALUE*)realloc(cars, capacity * sizeof(IC_VALUE));
+			}
+			cars[len] = IC_read(reader);
+			/* Values under construction are rooted so that a collection can't free them */
+			IC_root_push(cars[len++]);
+			IC_read_expect(reader, '.', "expected '.'");
+			IC_read_skip_whitespace(reader);
+		} while (*reader->curr == '(');
+
+		IC_VALUE value = IC_read(reader);
+		IC_root_push(value);
+		for (usize i = 0; i < len; i++) {
+			IC_read_expect(reader, ')', "expected ')'");
+		}
+
+		for (usize i = len; i > 0; i--) {
+			value = IC_cons(cars[i - 1], value);
+			IC_root_pop(1);
+			IC_root_push(value);
+		}
+		IC_root_pop(len + 1);
+		free(cars);
+		return value;
+	} else if (c == '[') {
+		reader->curr++;
+		usize len = 0;
+		usize capacity = 16;
+		IC_VALUE* elements = (IC_VALUE*)malloc(capacity * sizeof(IC_VALUE));
+		IC_read_skip_whitespace(reader);
+		while (*reader->curr != ']') {
+			if (len == capacity) {
+				capacity *= 2;
+				elements = (IC_VALUE*)realloc(elements, capacity * sizeof(IC_VALUE));
+			}
+			elements[len] = IC_read(reader);
+			IC_root_push(elements[len++]);
+			IC_read_skip_whitespace(reader);
+		}
+		reader->curr++;
+
+		IC_LAR_PROTO* array = IC_array_new(len, NULL);
+		for (usize i = 0; i < len; i++) {
+			IC_ARRAY_VALUE(array, i) = elements[i];
+		}
+		IC_root_pop(len);
+		free(elements);
+		return IC_ARRAY(array);
+	} else if (c == '\0') {
+		IC_read_error(reader, "unexpected end of input");
+	} else {
+		IC_read_error(reader, "expected a value");
+	}
+}
+
+static void IC_read_skip_whitespace(IC_READER* reader)
+{
+	while (isspace((unsigned char)*reader->curr)) {
+		reader->curr++;
+	}
+}
+
+__attribute__((noreturn)) static void IC_read_error(IC_READER* reader, const char* message)
+{
+	usize line = 1;
+	usize col = 1;
+	for (const char* c = reader->source; c < reader->curr; c++) {
+		if (*c == '\n') {
+			line++;
+			col = 1;
+		} else {
+			col++;
+		}
+	}
+	IC_runtime_error("%s:%lu:%lu: %s", reader->path, line, col, message);
 }
 
 static const char* IC_value_show_type(IC_VALUE value)
@@ -139,7 +765,26 @@ static const char* IC_value_show_type(IC_VALUE value)
 		return "atom";
 	case IC_VALUE_PAIR:
 		return "pair";
+	case IC_VALUE_ARRAY:
+		return "array";
+	case IC_VALUE_RECORD:
+		return "record";
 	default:
 		IC_runtime_error("unknown value type", 0);
 	}
 }
+
+/* Each unit of a split program has its own copy of the shapes, which are the same if their ids are */
+static int IC_shape_compare(const IC_SHAPE* a, const IC_SHAPE* b)
+{
+	if (a->id == b->id) {
+		return 0;
+	}
+	for (u8 i = 0; i < a->num_of_fields && i < b->num_of_fields; i++) {
+		int field = strcmp(a->fields[i], b->fields[i]);
+		if (field != 0) {
+			return field;
+		}
+	}
+	return a->num_of_fields < b->num_of_fields ? -1 : a->num_of_fields > b->num_of_fields;
+}